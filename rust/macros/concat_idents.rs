@@ -1,23 +1,145 @@
 // SPDX-License-Identifier: GPL-2.0
 
-use proc_macro::{token_stream, Ident, TokenStream, TokenTree};
+use proc_macro::{token_stream, Delimiter, Ident, Literal, Span, TokenStream, TokenTree};
+use std::iter::Peekable;
 
-use crate::helpers::expect_punct;
+/// Returns the text and span of the next segment, which is either an identifier or a decimal
+/// integer literal without suffix.
+///
+/// For a path, e.g. `module::CONST`, the segment is its last component.
+fn expect_segment(it: &mut Peekable<token_stream::IntoIter>) -> (String, Span, bool) {
+    match it.next() {
+        // Literals passed through `macro_rules!` fragments are wrapped in an invisible group.
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::None => {
+            let mut inner = group.stream().into_iter().peekable();
+            let segment = expect_segment(&mut inner);
+            assert!(inner.next().is_none(), "Expected Ident or integer literal");
+            segment
+        }
+        Some(TokenTree::Ident(mut ident)) => {
+            while matches!(it.peek(), Some(TokenTree::Punct(p)) if p.as_char() == ':') {
+                it.next();
+                match (it.next(), it.next()) {
+                    (Some(TokenTree::Punct(p)), Some(TokenTree::Ident(next)))
+                        if p.as_char() == ':' =>
+                    {
+                        ident = next
+                    }
+                    _ => panic!("Expected a path consisting of identifiers only"),
+                }
+            }
+            if matches!(it.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '!') {
+                return expand_nested(&ident, it);
+            }
+            (ident.to_string(), ident.span(), false)
+        }
+        Some(TokenTree::Literal(lit)) if lit.to_string().bytes().all(|b| b.is_ascii_digit()) => {
+            (lit.to_string(), lit.span(), true)
+        }
+        // The result could not be used, since macros cannot be invoked in lifetime positions.
+        Some(TokenTree::Punct(punct)) if punct.as_char() == '\'' => panic!(
+            "Lifetimes cannot be concatenated, since a macro cannot be used where a lifetime is \
+            expected"
+        ),
+        _ => panic!("Expected Ident or integer literal"),
+    }
+}
 
-fn expect_ident(it: &mut token_stream::IntoIter) -> Ident {
-    if let Some(TokenTree::Ident(ident)) = it.next() {
-        ident
-    } else {
-        panic!("Expected Ident")
+/// Expands a nested invocation of `concat_idents!` or `concat_idents_spanned!` named `name` and
+/// returns its result as a segment.
+///
+/// The outer macro is expanded first, so the inner one would otherwise be an invalid segment.
+fn expand_nested(name: &Ident, it: &mut Peekable<token_stream::IntoIter>) -> (String, Span, bool) {
+    it.next();
+    let args = match it.next() {
+        Some(TokenTree::Group(group)) if group.delimiter() != Delimiter::None => group.stream(),
+        _ => panic!("Expected the arguments of `{name}!`"),
+    };
+    let res = match name.to_string().as_str() {
+        "concat_idents" => concat_idents(args),
+        "concat_idents_spanned" => concat_idents_spanned(args),
+        _ => panic!(
+            "Only `concat_idents!` and `concat_idents_spanned!` can be nested, not `{name}!`"
+        ),
+    };
+    match res.into_iter().next() {
+        Some(TokenTree::Ident(ident)) => (ident.to_string(), ident.span(), false),
+        Some(TokenTree::Literal(lit)) => (lit.to_string(), lit.span(), true),
+        _ => unreachable!(),
+    }
+}
+
+/// Keywords that can be used as raw identifiers.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+
+/// Expects the next token to be the punctuation `c`.
+fn expect_separator(it: &mut Peekable<token_stream::IntoIter>, c: char) {
+    match it.next() {
+        Some(TokenTree::Punct(punct)) if punct.as_char() == c => (),
+        _ => panic!("Expected '{c}'"),
     }
 }
 
+/// Concatenates `segments` into an identifier with the given span.
+///
+/// If the first segment is an integer literal, the result is an integer literal as well.
+fn concat(segments: &[(String, Span, bool)], span: Span) -> TokenStream {
+    // Keywords are identifiers as well and are concatenated by their text, e.g. `mut`. Raw
+    // identifiers are concatenated without their `r#` prefix.
+    let name: String = segments
+        .iter()
+        .map(|(s, _, _)| s.strip_prefix("r#").unwrap_or(s))
+        .collect();
+    let res = if segments[0].2 {
+        // Identifiers cannot start with a digit, so only integers can be concatenated to one.
+        assert!(
+            segments.iter().all(|(_, _, is_int)| *is_int),
+            "an integer literal can only be followed by an integer literal"
+        );
+        let mut lit = Literal::u128_unsuffixed(
+            name.parse()
+                .unwrap_or_else(|_| panic!("`{name}` is not a valid integer literal")),
+        );
+        lit.set_span(span);
+        TokenTree::Literal(lit)
+    } else if KEYWORDS.contains(&name.as_str()) {
+        TokenTree::Ident(Ident::new_raw(&name, span))
+    } else {
+        TokenTree::Ident(Ident::new(&name, span))
+    };
+    TokenStream::from_iter([res])
+}
+
 pub(crate) fn concat_idents(ts: TokenStream) -> TokenStream {
-    let mut it = ts.into_iter();
-    let a = expect_ident(&mut it);
-    assert_eq!(expect_punct(&mut it), ',');
-    let b = expect_ident(&mut it);
+    let mut it = ts.into_iter().peekable();
+    let a = expect_segment(&mut it);
+    expect_separator(&mut it, ',');
+    let b = expect_segment(&mut it);
     assert!(it.next().is_none(), "only two idents can be concatenated");
-    let res = Ident::new(&format!("{a}{b}"), b.span());
-    TokenStream::from_iter([TokenTree::Ident(res)])
+    let span = b.1;
+    concat(&[a, b], span)
+}
+
+pub(crate) fn concat_idents_spanned(ts: TokenStream) -> TokenStream {
+    let mut it = ts.into_iter().peekable();
+    let span = it
+        .next()
+        .expect("Expected the token to take the span from")
+        .span();
+    expect_separator(&mut it, ';');
+    let mut segments = vec![expect_segment(&mut it)];
+    while let Some(token) = it.next() {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => (),
+            _ => panic!("Expected ','"),
+        }
+        segments.push(expect_segment(&mut it));
+    }
+    concat(&segments, span)
 }
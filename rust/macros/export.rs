@@ -1,29 +1,726 @@
 // SPDX-License-Identifier: GPL-2.0
 
-use crate::helpers::function_name;
-use proc_macro::TokenStream;
+use crate::helpers::{compile_error, function_name, split_commas};
+use proc_macro::{Delimiter, Group, Ident, Literal, TokenStream, TokenTree};
+
+/// The arguments of the `#[export]` attribute.
+#[derive(Default)]
+struct ExportArgs {
+    /// The linker section in which a pointer to the function is placed.
+    register: Option<String>,
+    /// The linker section in which the function itself is placed.
+    section: Option<String>,
+    /// The `#[repr(transparent)]` wrappers in the signature, together with their inner type.
+    transparent: Vec<(String, TokenStream)>,
+    /// Whether the signature is also verified in a test.
+    test_sig: bool,
+    /// The calling convention of the function, `C` if not given.
+    abi: Option<String>,
+}
+
+/// The calling conventions that exported functions can use.
+const ABIS: &[&str] = &[
+    "C", "C-unwind", "system", "sysv64", "win64", "efiapi", "aapcs",
+];
+
+impl ExportArgs {
+    fn parse(attr: TokenStream) -> Result<Self, String> {
+        const EXPECTED_KEYS: &[&str] = &["register", "section", "transparent", "test_sig", "abi"];
+
+        let mut args = ExportArgs::default();
+        let mut it = attr.into_iter();
+        while let Some(key) = it.next() {
+            let TokenTree::Ident(key) = key else {
+                return Err(format!("Expected one of {EXPECTED_KEYS:?}, found `{key}`."));
+            };
+            let key = key.to_string();
+            if key == "test_sig" {
+                args.test_sig = true;
+                match it.next() {
+                    Some(TokenTree::Punct(p)) if p.as_char() == ',' => continue,
+                    None => break,
+                    _ => return Err("Expected `,` or end of arguments.".to_string()),
+                }
+            }
+            if key == "transparent" {
+                match it.next() {
+                    Some(TokenTree::Group(group))
+                        if group.delimiter() == Delimiter::Parenthesis =>
+                    {
+                        args.transparent = parse_transparent(group.stream())?;
+                    }
+                    _ => return Err(TRANSPARENT_USAGE.to_string()),
+                }
+                match it.next() {
+                    Some(TokenTree::Punct(p)) if p.as_char() == ',' => continue,
+                    None => break,
+                    _ => return Err("Expected `,` or end of arguments.".to_string()),
+                }
+            }
+            if !matches!(it.next(), Some(TokenTree::Punct(p)) if p.as_char() == '=') {
+                return Err(format!("Expected `=` after `{key}`."));
+            }
+            let value = match it.next() {
+                Some(TokenTree::Literal(lit)) => lit.to_string(),
+                _ => return Err(format!("Expected a string literal for `{key}`.")),
+            };
+            let Some(value) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+                return Err(format!("Expected a string literal for `{key}`."));
+            };
+            match key.as_str() {
+                "register" => args.register = Some(section_name(value)?),
+                "section" => args.section = Some(section_name(value)?),
+                "abi" => {
+                    if !ABIS.contains(&value) {
+                        return Err(format!(
+                            "Unsupported ABI \"{value}\". Supported ABIs are: {ABIS:?}."
+                        ));
+                    }
+                    args.abi = Some(value.to_string());
+                }
+                _ => {
+                    return Err(format!(
+                        "Unknown key `{key}`. Valid keys are: {EXPECTED_KEYS:?}."
+                    ))
+                }
+            }
+            match it.next() {
+                Some(TokenTree::Punct(p)) if p.as_char() == ',' => {}
+                None => break,
+                _ => return Err("Expected `,` or end of arguments.".to_string()),
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Validates the name of a linker section.
+fn section_name(value: &str) -> Result<String, String> {
+    if value.is_empty()
+        || !value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"._$".contains(&b))
+    {
+        return Err(format!("Invalid section name \"{value}\"."));
+    }
+    Ok(value.to_string())
+}
+
+const TRANSPARENT_USAGE: &str =
+    "Expected a list of wrappers and their inner types, e.g. `transparent(Fd = c_int)`.";
+
+/// Parses the `Wrapper = Inner` pairs of the `transparent(...)` argument.
+fn parse_transparent(input: TokenStream) -> Result<Vec<(String, TokenStream)>, String> {
+    let mut wrappers: Vec<(String, TokenStream)> = Vec::new();
+    for item in split_commas(input) {
+        let [TokenTree::Ident(wrapper), TokenTree::Punct(eq), inner @ ..] = &item[..] else {
+            return Err(TRANSPARENT_USAGE.to_string());
+        };
+        if eq.as_char() != '=' || inner.is_empty() {
+            return Err(TRANSPARENT_USAGE.to_string());
+        }
+        let wrapper = wrapper.to_string();
+        if wrappers.iter().any(|(w, _)| *w == wrapper) {
+            return Err(format!("Duplicated wrapper `{wrapper}`."));
+        }
+        wrappers.push((wrapper, inner.iter().cloned().collect()));
+    }
+    if wrappers.is_empty() {
+        return Err(TRANSPARENT_USAGE.to_string());
+    }
+    Ok(wrappers)
+}
+
+/// Replaces the wrappers in `input` by their inner types.
+fn replace_wrappers(input: TokenStream, wrappers: &[(String, TokenStream)]) -> TokenStream {
+    input
+        .into_iter()
+        .flat_map(|tt| match tt {
+            TokenTree::Ident(ref i) => match wrappers.iter().find(|(w, _)| *w == i.to_string()) {
+                Some((_, inner)) => inner.clone(),
+                None => TokenStream::from(tt),
+            },
+            TokenTree::Group(group) => {
+                let mut new = Group::new(
+                    group.delimiter(),
+                    replace_wrappers(group.stream(), wrappers),
+                );
+                new.set_span(group.span());
+                TokenStream::from(TokenTree::Group(new))
+            }
+            tt => TokenStream::from(tt),
+        })
+        .collect()
+}
+
+/// Given a struct declaration, returns its name, visibility and the type of its only field, if it
+/// is a non-generic `#[repr(transparent)]` wrapper.
+fn transparent_struct(input: TokenStream) -> Result<(Ident, TokenStream, TokenStream), String> {
+    let mut input = input.into_iter().peekable();
+    let mut is_transparent = false;
+    while let Some(TokenTree::Punct(p)) = input.peek() {
+        if p.as_char() != '#' {
+            break;
+        }
+        input.next();
+        let Some(TokenTree::Group(attr)) = input.next() else {
+            break;
+        };
+        let mut attr = attr.stream().into_iter();
+        if matches!(attr.next(), Some(TokenTree::Ident(i)) if i.to_string() == "repr") {
+            if let Some(TokenTree::Group(reprs)) = attr.next() {
+                is_transparent |= reprs
+                    .stream()
+                    .into_iter()
+                    .any(|tt| matches!(tt, TokenTree::Ident(i) if i.to_string() == "transparent"));
+            }
+        }
+    }
+    let vis: TokenStream = input
+        .by_ref()
+        .take_while(|tt| !matches!(tt, TokenTree::Ident(i) if i.to_string() == "struct"))
+        .collect();
+    let Some(TokenTree::Ident(name)) = input.next() else {
+        return Err("Expected the name of the struct.".to_string());
+    };
+    if !is_transparent {
+        return Err(format!(
+            "`{name}` must be `#[repr(transparent)]` to be used in the signature of an exported \
+                function."
+        ));
+    }
+    let fields = match input.next() {
+        Some(TokenTree::Group(fields)) => split_commas(fields.stream()),
+        Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+            return Err(format!(
+                "`{name}` must not be generic to be used in the signature of an exported function."
+            ))
+        }
+        _ => Vec::new(),
+    };
+    let [field] = &fields[..] else {
+        return Err(format!(
+            "`{name}` must have exactly one field to be used in the signature of an exported \
+                function."
+        ));
+    };
+    // The type follows the `:` in a named field, or the attributes and visibility in a tuple
+    // field.
+    let ty = match field
+        .iter()
+        .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ':'))
+    {
+        Some(colon) => field[colon + 1..].iter().cloned().collect(),
+        None => {
+            let mut field = field.iter().peekable();
+            while let Some(TokenTree::Punct(p)) = field.peek() {
+                if p.as_char() != '#' {
+                    break;
+                }
+                field.nth(1);
+            }
+            if matches!(field.peek(), Some(TokenTree::Ident(i)) if i.to_string() == "pub") {
+                field.next();
+                // Restricted visibility, e.g. `pub(crate)`.
+                if let Some(TokenTree::Group(g)) = field.peek() {
+                    if g.delimiter() == Delimiter::Parenthesis {
+                        field.next();
+                    }
+                }
+            }
+            field.cloned().collect()
+        }
+    };
+    Ok((name, vis, ty))
+}
+
+/// Marks a `#[repr(transparent)]` struct as usable in the signature of exported functions.
+fn export_struct(ts: TokenStream) -> TokenStream {
+    let (name, vis, ty) = match transparent_struct(ts.clone()) {
+        Ok(parsed) => parsed,
+        Err(msg) => return compile_error(&msg),
+    };
+    let marker = quote!(
+        impl #name {
+            #[doc(hidden)]
+            #vis fn __export_transparent(_: #ty) {}
+        }
+    );
+    TokenStream::from_iter([ts, marker])
+}
+
+/// Returns the keyword of the item, i.e. `struct`, `fn` or `mod`, if any.
+fn item_kind(input: TokenStream) -> Option<String> {
+    input.into_iter().find_map(|tt| match tt {
+        TokenTree::Ident(i) if ["struct", "fn", "mod"].contains(&&*i.to_string()) => {
+            Some(i.to_string())
+        }
+        _ => None,
+    })
+}
+
+/// Splits the body of a module into its items.
+///
+/// Items end with a `;` or with their body, e.g. functions, while constants and statics end with a
+/// `;` even if their value contains a block.
+fn module_items(body: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut items = Vec::new();
+    let mut item = Vec::new();
+    let mut tokens = body.into_iter().peekable();
+    while let Some(tt) = tokens.next() {
+        let ends = match &tt {
+            TokenTree::Punct(p) => p.as_char() == ';',
+            TokenTree::Group(g) if g.delimiter() == Delimiter::Brace => {
+                // The keywords of the item, attributes and restricted visibilities are groups.
+                let keywords: Vec<_> = item
+                    .iter()
+                    .filter_map(|tt| match tt {
+                        TokenTree::Ident(i) if i.to_string() != "pub" => Some(i.to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                match keywords.first().map(String::as_str) {
+                    Some("const") => matches!(
+                        keywords.get(1).map(String::as_str),
+                        Some("fn" | "unsafe" | "extern" | "async")
+                    ),
+                    Some("static" | "use" | "type") => false,
+                    _ => true,
+                }
+            }
+            _ => false,
+        };
+        item.push(tt);
+        if ends {
+            // Macro invocations with braces may be followed by a `;`.
+            if matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == ';') {
+                item.extend(tokens.next());
+            }
+            items.push(std::mem::take(&mut item));
+        }
+    }
+    if !item.is_empty() {
+        items.push(item);
+    }
+    items
+}
+
+/// Returns whether the item has an `#[export]` attribute itself.
+fn has_export_attr(input: TokenStream) -> bool {
+    input.into_iter().any(|tt| match tt {
+        TokenTree::Group(attr) if attr.delimiter() == Delimiter::Bracket => attr
+            .stream()
+            .into_iter()
+            .take_while(|tt| !matches!(tt, TokenTree::Group(_)))
+            .any(|tt| matches!(tt, TokenTree::Ident(i) if i.to_string() == "export")),
+        _ => false,
+    })
+}
+
+/// Exports every `extern` function of an inline module with the arguments of `#[export]`.
+///
+/// Other items, including functions with the Rust ABI, e.g. helpers, are kept as they are.
+fn export_module(attr: TokenStream, ts: TokenStream) -> TokenStream {
+    if let Err(msg) = ExportArgs::parse(attr.clone()) {
+        return compile_error(&msg);
+    }
+    let mut tokens: Vec<_> = ts.into_iter().collect();
+    let body = match tokens.pop() {
+        Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Brace => body,
+        _ => return compile_error("The #[export] attribute can only be used on inline modules."),
+    };
+    let mut new_body = TokenStream::new();
+    for item in module_items(body.stream()) {
+        let item: TokenStream = item.into_iter().collect();
+        if item_kind(item.clone()).as_deref() == Some("fn")
+            && declared_abi(item.clone()).is_some()
+            && !has_export_attr(item.clone())
+        {
+            new_body.extend(export(attr.clone(), item));
+        } else {
+            new_body.extend(item);
+        }
+    }
+    let mut new_body = Group::new(Delimiter::Brace, new_body);
+    new_body.set_span(body.span());
+    tokens.push(TokenTree::Group(new_body));
+    tokens.into_iter().collect()
+}
+
+/// Given a function declaration, returns its `#[cfg(...)]` attributes.
+///
+/// These need to be applied to the generated items as well, so that they are removed together
+/// with the function.
+fn cfg_attrs(input: TokenStream) -> TokenStream {
+    let mut attrs = TokenStream::new();
+    let mut input = input.into_iter().peekable();
+    while let Some(TokenTree::Punct(p)) = input.peek() {
+        if p.as_char() != '#' {
+            break;
+        }
+        let hash = input.next().unwrap();
+        let Some(TokenTree::Group(attr)) = input.next() else {
+            break;
+        };
+        let name = attr.stream().into_iter().next();
+        if matches!(name, Some(TokenTree::Ident(i)) if i.to_string() == "cfg") {
+            attrs.extend([hash, TokenTree::Group(attr)]);
+        }
+    }
+    attrs
+}
+
+/// Given a function declaration, returns the ABI given with `extern`, if any.
+fn declared_abi(input: TokenStream) -> Option<String> {
+    let mut input = input
+        .into_iter()
+        .take_while(|tt| !matches!(tt, TokenTree::Ident(i) if i.to_string() == "fn"))
+        .skip_while(|tt| !matches!(tt, TokenTree::Ident(i) if i.to_string() == "extern"));
+    input.next()?;
+    // `extern` without an ABI is `extern "C"`.
+    match input.next() {
+        Some(TokenTree::Literal(lit)) => Some(lit.to_string().trim_matches('"').to_string()),
+        _ => Some("C".to_string()),
+    }
+}
+
+/// Given a function declaration, returns whether it is an `async fn`.
+fn is_async(input: TokenStream) -> bool {
+    input
+        .into_iter()
+        .take_while(|tt| !matches!(tt, TokenTree::Ident(i) if i.to_string() == "fn"))
+        .any(|tt| matches!(tt, TokenTree::Ident(i) if i.to_string() == "async"))
+}
+
+/// Given a function declaration, returns whether it has type or const generic parameters.
+///
+/// Lifetime parameters are fine, since they do not result in more than one symbol.
+fn is_generic(input: TokenStream) -> bool {
+    let mut input = input
+        .into_iter()
+        .skip_while(|tt| !matches!(tt, TokenTree::Ident(i) if i.to_string() == "fn"))
+        .skip(2)
+        .peekable();
+    if matches!(input.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+        input.next();
+        // The current level of `<`.
+        let mut nesting = 1;
+        let mut at_start = true;
+        for tt in input.by_ref() {
+            match tt {
+                TokenTree::Punct(p) if p.as_char() == '<' => nesting += 1,
+                TokenTree::Punct(p) if p.as_char() == '>' => {
+                    nesting -= 1;
+                    if nesting == 0 {
+                        break;
+                    }
+                }
+                TokenTree::Punct(p) if p.as_char() == ',' && nesting == 1 => at_start = true,
+                // Lifetimes begin with `'`.
+                TokenTree::Punct(p) if p.as_char() == '\'' && at_start => at_start = false,
+                _ if at_start => return true,
+                _ => (),
+            }
+        }
+    }
+    // Parameters of type `impl Trait` are generic as well.
+    match input.next() {
+        Some(TokenTree::Group(params)) => params
+            .stream()
+            .into_iter()
+            .any(|tt| matches!(tt, TokenTree::Ident(i) if i.to_string() == "impl")),
+        _ => false,
+    }
+}
+
+/// Owned Rust types whose layout C cannot know, e.g. because they contain a pointer, a length and a
+/// capacity.
+const NON_FFI_SAFE_TYPES: &[&str] = &[
+    "String", "Vec", "KVec", "VVec", "KVVec", "CString", "BTreeMap", "HashMap", "RBTree",
+];
+
+/// Returns a description of why `ty` is obviously not FFI-safe, if it is.
+///
+/// This is a best-effort syntactic check for common mistakes, the signature check against the
+/// C declaration remains the authoritative one.
+fn non_ffi_safe(ty: TokenStream) -> Option<String> {
+    let tokens: Vec<_> = ty.into_iter().collect();
+    for token in &tokens {
+        match token {
+            TokenTree::Ident(i) if NON_FFI_SAFE_TYPES.contains(&i.to_string().as_str()) => {
+                return Some(format!(
+                    "`{i}` is an owned Rust type with an unspecified layout"
+                ));
+            }
+            TokenTree::Ident(i) if i.to_string() == "str" => {
+                return Some(
+                    "`str` is not FFI-safe, a reference to it consists of a pointer and a length"
+                        .to_string(),
+                );
+            }
+            TokenTree::Ident(i) if i.to_string() == "dyn" => {
+                return Some(
+                    "trait objects are not FFI-safe, a pointer to one consists of a data and a \
+                        vtable pointer"
+                        .to_string(),
+                );
+            }
+            // A slice, in contrast to an array `[T; N]`, has no `;`.
+            TokenTree::Group(g)
+                if g.delimiter() == Delimiter::Bracket
+                    && !g
+                        .stream()
+                        .into_iter()
+                        .any(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ';')) =>
+            {
+                return Some(
+                    "slices are not FFI-safe, a pointer to one consists of a pointer and a length"
+                        .to_string(),
+                );
+            }
+            TokenTree::Group(g) => {
+                if let Some(reason) = non_ffi_safe(g.stream()) {
+                    return Some(reason);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Given a function declaration, returns the types of its parameters and its return type, if any.
+fn signature_types(input: TokenStream) -> Vec<TokenStream> {
+    let mut input = input
+        .into_iter()
+        .skip_while(|tt| !matches!(tt, TokenTree::Ident(i) if i.to_string() == "fn"));
+    let mut types = Vec::new();
+    // The parameters follow the name and the lifetime parameters, if any.
+    let params = input.find_map(|tt| match tt {
+        TokenTree::Group(g) if g.delimiter() == Delimiter::Parenthesis => Some(g),
+        _ => None,
+    });
+    if let Some(params) = params {
+        for param in split_commas(params.stream()) {
+            // The type follows the `:` after the pattern.
+            if let Some(colon) = param
+                .iter()
+                .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ':'))
+            {
+                types.push(param[colon + 1..].iter().cloned().collect());
+            }
+        }
+    }
+    // The return type follows the `->` and ends at the body.
+    let ret: TokenStream = input
+        .skip_while(|tt| !matches!(tt, TokenTree::Punct(p) if p.as_char() == '>'))
+        .skip(1)
+        .take_while(|tt| !matches!(tt, TokenTree::Group(g) if g.delimiter() == Delimiter::Brace))
+        .collect();
+    if !ret.is_empty() {
+        types.push(ret);
+    }
+    types
+}
+
+/// Given a function declaration, builds the type of a pointer to that function.
+///
+/// The pointer is always `unsafe`, since a safe function coerces into an unsafe function pointer.
+/// The lifetime parameters of the function are bound with `for<...>` and its `where` clause is
+/// left out.
+fn function_pointer_type(input: TokenStream, abi: &str) -> TokenStream {
+    let mut input = input
+        .into_iter()
+        .skip_while(|tt| !matches!(tt, TokenTree::Ident(i) if i.to_string() == "fn"))
+        .skip(2)
+        .peekable();
+    let mut ty = TokenStream::new();
+    // Only lifetime parameters can follow, other generics are rejected by `is_generic`. Their
+    // bounds are dropped, since they cannot be given in `for<...>`.
+    if matches!(input.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+        input.next();
+        let mut lifetimes = Vec::new();
+        let mut at_start = true;
+        for tt in input.by_ref() {
+            match tt {
+                TokenTree::Punct(p) if p.as_char() == '>' => break,
+                TokenTree::Punct(p) if p.as_char() == ',' => at_start = true,
+                TokenTree::Ident(i) if at_start => {
+                    lifetimes.push(format!("'{i}"));
+                    at_start = false;
+                }
+                _ => (),
+            }
+        }
+        ty.extend(format!("for<{}>", lifetimes.join(", ")).parse::<TokenStream>());
+    }
+    ty.extend(format!("unsafe extern {abi:?} fn").parse::<TokenStream>());
+    if let Some(TokenTree::Group(params)) = input.next() {
+        // Parameter names are allowed in function pointer types, but not `mut` bindings. These
+        // start a parameter, unlike the `mut` of a pointer or reference type.
+        let mut param_start = true;
+        let params = params
+            .stream()
+            .into_iter()
+            .filter(|tt| {
+                let is_binding =
+                    param_start && matches!(tt, TokenTree::Ident(i) if i.to_string() == "mut");
+                param_start = matches!(tt, TokenTree::Punct(p) if p.as_char() == ',');
+                !is_binding
+            })
+            .collect();
+        ty.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, params))]);
+    }
+    // The return type is everything up to the `where` clause or the body.
+    ty.extend(input.take_while(|tt| match tt {
+        TokenTree::Group(g) => g.delimiter() != Delimiter::Brace,
+        TokenTree::Ident(i) => i.to_string() != "where",
+        _ => true,
+    }));
+    ty
+}
 
 /// Please see [`crate::export`] for documentation.
-pub(crate) fn export(_attr: TokenStream, ts: TokenStream) -> TokenStream {
+pub(crate) fn export(attr: TokenStream, ts: TokenStream) -> TokenStream {
+    match item_kind(ts.clone()).as_deref() {
+        Some("struct") => {
+            if !attr.is_empty() {
+                return compile_error(
+                    "The #[export] attribute does not take arguments on a struct.",
+                );
+            }
+            return export_struct(ts);
+        }
+        Some("mod") => return export_module(attr, ts),
+        _ => (),
+    }
+
     let Some(name) = function_name(ts.clone()) else {
-        return "::core::compile_error!(\"The #[export] attribute must be used on a function.\");"
-            .parse::<TokenStream>()
-            .unwrap();
+        return compile_error("The #[export] attribute must be used on a function.");
+    };
+
+    if is_async(ts.clone()) {
+        return compile_error(
+            "Exported functions must not be `async`, since C cannot poll the returned future. \
+                Export a blocking wrapper that runs the future to completion instead.",
+        );
+    }
+
+    if is_generic(ts.clone()) {
+        return compile_error(
+            "Exported functions must not be generic, since they need a single C symbol. Only \
+                lifetime parameters are allowed.",
+        );
+    }
+
+    for ty in signature_types(ts.clone()) {
+        if let Some(reason) = non_ffi_safe(ty) {
+            return compile_error(&format!(
+                "Exported functions must only use FFI-safe types, but {reason}."
+            ));
+        }
+    }
+
+    let args = match ExportArgs::parse(attr) {
+        Ok(args) => args,
+        Err(msg) => return compile_error(&msg),
     };
 
+    // The declaration generated by bindgen uses the calling convention of the C function, so a
+    // mismatch would be reported by the signature check below as well, but less clearly.
+    let abi = args.abi.as_deref().unwrap_or("C");
+    if declared_abi(ts.clone()).as_deref() != Some(abi) {
+        return compile_error(&format!(
+            "`{name}` must be declared `extern {abi:?}`, since it is exported with the `{abi}` \
+                calling convention. Other calling conventions can be given with \
+                `#[export(abi = \"...\")]`."
+        ));
+    }
+
+    let cfg = cfg_attrs(ts.clone());
+
     // This verifies that the function has the same signature as the declaration generated by
     // bindgen. It makes use of the fact that all branches of an if/else must have the same type.
-    let signature_check = quote!(
-        const _: () = {
+    // Function pointer parameters are compared structurally as well, hence callbacks need to be
+    // `Option<unsafe extern "C" fn(...)>` to match what bindgen generates for C function pointers.
+    let checks = if args.transparent.is_empty() {
+        quote!(
             if true {
                 ::kernel::bindings::#name
             } else {
                 #name
             };
+        )
+    } else {
+        // With wrappers in the signature, the declaration instead needs to coerce to the
+        // function pointer type in which the wrappers are replaced by their inner types. The
+        // wrappers need to be marked by `#[export]`, which checks that they are transparent.
+        let ty = function_pointer_type(ts.clone(), abi);
+        let c_ty = replace_wrappers(ty.clone(), &args.transparent);
+        let wrapper_checks: TokenStream = args
+            .transparent
+            .iter()
+            .map(|(wrapper, inner)| {
+                let inner = inner.clone();
+                let wrapper = TokenTree::Ident(Ident::new(wrapper, name.span()));
+                quote!(
+                    let _: fn(#inner) = #wrapper::__export_transparent;
+                )
+            })
+            .collect();
+        quote!(
+            let _: #c_ty = ::kernel::bindings::#name;
+            let _: #ty = #name;
+            #wrapper_checks
+        )
+    };
+    let mut signature_check = quote!(
+        #cfg
+        const _: () = {
+            #checks
         };
     );
+    if args.test_sig {
+        // The same checks as a test, so that the FFI boundary shows up in the test results.
+        let test_name = Ident::new(&format!("__export_test_sig_{name}"), name.span());
+        signature_check.extend(quote!(
+            #cfg
+            #[cfg(test)]
+            #[test]
+            fn #test_name() {
+                #checks
+            }
+        ));
+    }
+
+    // Functions discovered by C through a table in a linker section get a pointer placed there.
+    let registration = args.register.map(|section| {
+        let section = TokenTree::Literal(Literal::string(&section));
+        let ty = function_pointer_type(ts.clone(), abi);
+        quote!(
+            #cfg
+            const _: () = {
+                #[used]
+                #[link_section = #section]
+                static __EXPORT_REGISTRATION: #ty = #name;
+            };
+        )
+    });
 
     let no_mangle = quote!(#[no_mangle]);
 
-    TokenStream::from_iter([signature_check, no_mangle, ts])
+    // Like C functions annotated with e.g. `__init`, the function can be placed in a section that
+    // is discarded later.
+    let section = args.section.map(|section| {
+        let section = TokenTree::Literal(Literal::string(&section));
+        quote!(#[link_section = #section])
+    });
+
+    TokenStream::from_iter([
+        signature_check,
+        registration.unwrap_or_default(),
+        no_mangle,
+        section.unwrap_or_default(),
+        ts,
+    ])
 }
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: GPL-2.0
 
-use proc_macro::{token_stream, Group, Ident, TokenStream, TokenTree};
+use proc_macro::{token_stream, Delimiter, Group, Ident, TokenStream, TokenTree};
 
 pub(crate) fn try_ident(it: &mut token_stream::IntoIter) -> Option<String> {
     if let Some(TokenTree::Ident(ident)) = it.next() {
@@ -56,6 +56,14 @@ pub(crate) fn expect_string_ascii(it: &mut token_stream::IntoIter) -> String {
     string
 }
 
+pub(crate) fn expect_bool(it: &mut token_stream::IntoIter) -> bool {
+    match expect_ident(it).as_str() {
+        "true" => true,
+        "false" => false,
+        _ => panic!("Expected `true` or `false`"),
+    }
+}
+
 pub(crate) fn expect_group(it: &mut token_stream::IntoIter) -> Group {
     if let TokenTree::Group(group) = it.next().expect("Reached end of token stream for Group") {
         group
@@ -216,6 +224,37 @@ pub(crate) fn parse_generics(input: TokenStream) -> (Generics, Vec<TokenTree>) {
     )
 }
 
+/// Splits `input` at the commas that are not nested in `<>`.
+///
+/// The `>` of a `->` is not treated as a closing bracket, so function pointer types can be split
+/// as well. A trailing comma is allowed.
+pub(crate) fn split_commas(input: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut items = vec![Vec::new()];
+    // The current level of `<`.
+    let mut nesting = 0;
+    let mut after_minus = false;
+    for tt in input {
+        match &tt {
+            TokenTree::Punct(p) if p.as_char() == '<' => nesting += 1,
+            TokenTree::Punct(p) if p.as_char() == '>' && !after_minus && nesting > 0 => {
+                nesting -= 1
+            }
+            TokenTree::Punct(p) if p.as_char() == ',' && nesting == 0 => {
+                items.push(Vec::new());
+                after_minus = false;
+                continue;
+            }
+            _ => (),
+        }
+        after_minus = matches!(&tt, TokenTree::Punct(p) if p.as_char() == '-');
+        items.last_mut().unwrap().push(tt);
+    }
+    if items.last().is_some_and(|item| item.is_empty()) {
+        items.pop();
+    }
+    items
+}
+
 /// Given a function declaration, finds the name of the function.
 pub(crate) fn function_name(input: TokenStream) -> Option<Ident> {
     let mut input = input.into_iter();
@@ -232,3 +271,104 @@ pub(crate) fn function_name(input: TokenStream) -> Option<Ident> {
     }
     None
 }
+
+/// Returns a `compile_error!` invocation with the message `msg`.
+pub(crate) fn compile_error(msg: &str) -> TokenStream {
+    format!("::core::compile_error!({msg:?});")
+        .parse::<TokenStream>()
+        .unwrap()
+}
+
+/// Builds an item that asserts at compile-time that types implement traits.
+///
+/// The types keep their spans, so an error points at the type in the input of the macro rather
+/// than at the whole invocation. The assertions are generic over the given generics, so they can
+/// name the type parameters of the input.
+///
+/// # Examples
+///
+/// Assertions of the same trait share one helper function:
+///
+/// ```rust,ignore
+/// let mut assertions = Assertions::new(quote!(T), quote!(where T: Copy));
+/// assertions.implements(quote!([T; 4]), "::kernel::init::Zeroable");
+/// assertions.implements(quote!(u32), "::kernel::init::Zeroable");
+/// let item: TokenStream = assertions.build();
+/// // const _: () = {
+/// //     fn __assert_impl_0<T: ?::core::marker::Sized + ::kernel::init::Zeroable>() {}
+/// //     fn __assertions<T>() where T: Copy {
+/// //         __assert_impl_0::<[T; 4]>();
+/// //         __assert_impl_0::<u32>();
+/// //     }
+/// // };
+/// ```
+///
+/// It is tested through the derive of `Zeroable` for structs and unions in the documentation of
+/// `derive_zeroable`.
+pub(crate) struct Assertions {
+    /// The generics with bounds of the assertions, without `<>`.
+    impl_generics: TokenStream,
+    /// The `where` clause of the assertions, including the `where` keyword, if any.
+    where_clause: TokenStream,
+    /// The paths of the traits that are asserted, the index of a trait names its helper function.
+    traits: Vec<String>,
+    /// The calls of the helper functions.
+    checks: TokenStream,
+}
+
+impl Assertions {
+    pub(crate) fn new(
+        impl_generics: impl IntoIterator<Item = TokenTree>,
+        where_clause: impl IntoIterator<Item = TokenTree>,
+    ) -> Self {
+        Self {
+            impl_generics: impl_generics.into_iter().collect(),
+            where_clause: where_clause.into_iter().collect(),
+            traits: Vec::new(),
+            checks: TokenStream::new(),
+        }
+    }
+
+    /// Asserts that `ty` implements the trait with the path `trait_path`.
+    pub(crate) fn implements(&mut self, ty: TokenStream, trait_path: &str) -> &mut Self {
+        let index = match self.traits.iter().position(|t| t == trait_path) {
+            Some(index) => index,
+            None => {
+                self.traits.push(trait_path.to_owned());
+                self.traits.len() - 1
+            }
+        };
+        self.checks
+            .extend(parse(&format!("__assert_impl_{index}::<")));
+        self.checks.extend(ty);
+        self.checks.extend(parse(">();"));
+        self
+    }
+
+    /// Returns the `const _: () = { ... };` item with the assertions.
+    pub(crate) fn build(self) -> TokenStream {
+        let mut body: TokenStream = self
+            .traits
+            .iter()
+            .enumerate()
+            .map(|(index, trait_path)| {
+                parse(&format!(
+                    "fn __assert_impl_{index}<T: ?::core::marker::Sized + {trait_path}>() {{}}"
+                ))
+            })
+            .collect();
+        body.extend(parse("fn __assertions<"));
+        body.extend(self.impl_generics);
+        body.extend(parse(">()"));
+        body.extend(self.where_clause);
+        body.extend([TokenTree::Group(Group::new(Delimiter::Brace, self.checks))]);
+        let mut item = parse("const _: () =");
+        item.extend([TokenTree::Group(Group::new(Delimiter::Brace, body))]);
+        item.extend(parse(";"));
+        item
+    }
+}
+
+fn parse(s: &str) -> TokenStream {
+    s.parse().unwrap()
+}
@@ -86,15 +86,388 @@
 /// # fn main() {}
 /// ```
 ///
+/// The firmware information is emitted for both loadable and built-in modules. Set
+/// `firmware_modular_only: true` to only emit it when the module is built as a loadable module.
+///
+/// Firmware of built-in modules is requested early during boot, so the files need to be built
+/// into the kernel image or the initramfs. Set `strict: true` to turn declaring `firmware` in a
+/// built-in configuration into a compile error, unless `firmware_modular_only` is set.
+///
+/// Listing a firmware file more than once is an error:
+///
+/// ```compile_fail
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyDeviceDriverModule,
+///     name: "my_device_driver_module",
+///     license: "GPL",
+///     firmware: [
+///         "my_device_firmware1.bin",
+///         "my_device_firmware2.bin",
+///         // error: Duplicated firmware "my_device_firmware1.bin" (entries 1 and 3).
+///         "my_device_firmware1.bin",
+///     ],
+/// }
+///
+/// struct MyDeviceDriverModule;
+///
+/// impl kernel::Module for MyDeviceDriverModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Device tree
+///
+/// Drivers of devices described by the device tree list the compatibles they match in the
+/// `of_match` field. For each of them, the `of:N*T*C<compatible>` aliases are emitted that modpost
+/// generates for a C `of_device_id` table, so that the module is loaded for a matching device.
+/// The compatibles are also available as the `OF_COMPATIBLES` constant of type
+/// `&[&'static CStr]`, e.g. to build the device table of the driver:
+///
+/// ```
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyDeviceDriverModule,
+///     name: "my_device_driver_module",
+///     license: "GPL",
+///     of_match: ["acme,widget", "acme,widget-v2"],
+/// }
+///
+/// struct MyDeviceDriverModule;
+///
+/// impl kernel::Module for MyDeviceDriverModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+///
+/// const _: () = assert!(OF_COMPATIBLES.len() == 2);
+/// # fn main() {}
+/// ```
+///
+/// A compatible needs a vendor prefix:
+///
+/// ```compile_fail
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyDeviceDriverModule,
+///     name: "my_device_driver_module",
+///     license: "GPL",
+///     // error: Invalid compatible "widget", expected "vendor,device".
+///     of_match: ["widget"],
+/// }
+///
+/// struct MyDeviceDriverModule;
+///
+/// impl kernel::Module for MyDeviceDriverModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Additional modinfo tags
+///
+/// Tags that the C side sets via `MODULE_INFO(tag, info)` can be given in the `info` field.
+/// Boolean values are normalized to `"Y"` and `"N"`, like the C build spells its flags. For
+/// example, the kernel checks the `test` tag of modules containing KUnit tests and taints itself
+/// with `TAINT_TEST` when loading them.
+///
+/// The tags that modpost generates itself, i.e. `intree`, `retpoline`, `staging`, `depends`,
+/// `vermagic` and `name`, cannot be given, since setting them by hand would add a second entry
+/// that e.g. hides that an out-of-tree module taints the kernel. The same applies to the tags that
+/// `module!` generates from its keys, e.g. `license`, `author`, `alias`, `firmware` and
+/// `livepatch`. They have to be given with the corresponding key, so that they are checked and
+/// not duplicated. The dependencies of a module are derived from the symbols it uses. Modules
+/// that have to be loaded before or after it without such a dependency are declared with the
+/// `softdep` tag in `info` instead, e.g. `info: { softdep: "pre: crc32c" }`, like
+/// `MODULE_SOFTDEP()` does in C.
+///
+/// ```
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyModule,
+///     name: "my_kernel_module",
+///     license: "GPL",
+///     info: {
+///         test: true,
+///         softdep: "pre: crc32c",
+///     },
+/// }
+///
+/// struct MyModule;
+///
+/// impl kernel::Module for MyModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyModule,
+///     name: "my_kernel_module",
+///     license: "GPL",
+///     // error: The info tag "intree" is generated by modpost and cannot be set by the module.
+///     info: {
+///         intree: true,
+///     },
+/// }
+///
+/// struct MyModule;
+///
+/// impl kernel::Module for MyModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyModule,
+///     name: "my_kernel_module",
+///     license: "Proprietary",
+///     // error: The info tag "license" is generated by `module!` from its keys and cannot be set
+///     // with `info`.
+///     info: {
+///         license: "GPL",
+///     },
+/// }
+///
+/// struct MyModule;
+///
+/// impl kernel::Module for MyModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```compile_fail
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyModule,
+///     name: "my_kernel_module",
+///     license: "GPL",
+///     // error: The info tag "livepatch" is generated by `module!` from its keys and cannot be set
+///     // with `info`.
+///     info: {
+///         livepatch: true,
+///     },
+/// }
+///
+/// struct MyModule;
+///
+/// impl kernel::Module for MyModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// The type given in `type` has to implement [`Module`], otherwise the error points at the type:
+///
+/// ```compile_fail
+/// use kernel::prelude::*;
+///
+/// module!{
+///     // error: the trait bound `MyModule: InPlaceModule` is not satisfied
+///     type: MyModule,
+///     name: "my_kernel_module",
+///     license: "GPL",
+/// }
+///
+/// struct MyModule;
+/// # fn main() {}
+/// ```
+///
+/// ## Generic module types
+///
+/// The type of a kernel module can be an instantiation of a generic type, e.g. to configure
+/// shared module logic with a type parameter:
+///
+/// ```
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyModule<Config>,
+///     name: "my_kernel_module",
+///     license: "GPL",
+/// }
+///
+/// trait Settings: Send + Sync {
+///     const VERBOSE: bool;
+/// }
+///
+/// struct Config;
+///
+/// impl Settings for Config {
+///     const VERBOSE: bool = true;
+/// }
+///
+/// struct MyModule<S: Settings>(core::marker::PhantomData<S>);
+///
+/// impl<S: Settings> kernel::Module for MyModule<S> {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         if S::VERBOSE {
+///             pr_info!("Loaded\n");
+///         }
+///         Ok(Self(core::marker::PhantomData))
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Data section
+///
+/// The instance of the kernel module type is stored in a static that is placed in the usual data
+/// section. With `data_section`, it is placed in the given linker section instead, e.g. for
+/// hardened kernels that protect a dedicated section. The section has to be writable, since the
+/// instance is initialized when the module is loaded, and has to be handled by the linker script:
+///
+/// ```
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyModule,
+///     name: "my_kernel_module",
+///     license: "GPL",
+///     data_section: ".data..rust_module",
+/// }
+///
+/// struct MyModule;
+///
+/// impl kernel::Module for MyModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Test stubs
+///
+/// With `test_stub: true`, the `init` and exit logic of a kernel module can be tested on the host,
+/// e.g. under `rusttest`. Instead of the kernel module glue, `module!` then generates a hidden
+/// `__module_test_stub` module with the `unsafe` functions `init()`, which initializes the module
+/// and returns `0` or a negative error code, and `exit()`, which drops the module again.
+///
+/// The stub has several limitations:
+///
+/// - It is only accepted in test builds (`cfg(test)`), production builds fail to compile.
+/// - No modinfo, initcall or exported symbols are emitted, so the result can neither be loaded nor
+///   built into the kernel.
+/// - `THIS_MODULE` does not refer to a `struct module`, so anything that dereferences it, e.g. to
+///   take a reference on the module, must not be used.
+/// - Only one instance of the module exists, so `init()` may only be called once per test binary.
+///
+/// ```ignore
+/// use kernel::prelude::*;
+///
+/// module!{
+///     type: MyModule,
+///     name: "my_kernel_module",
+///     license: "GPL",
+///     test_stub: true,
+/// }
+///
+/// struct MyModule;
+///
+/// impl kernel::Module for MyModule {
+///     fn init(_module: &'static ThisModule) -> Result<Self> {
+///         Ok(Self)
+///     }
+/// }
+///
+/// #[test]
+/// fn init_and_exit() {
+///     // SAFETY: `init` is only called once and `exit` only after `init` succeeded.
+///     unsafe {
+///         assert_eq!(__module_test_stub::init(), 0);
+///         __module_test_stub::exit();
+///     }
+/// }
+/// ```
+///
 /// # Supported argument types
-///   - `type`: type which implements the [`Module`] trait (required).
+///   - `type`: type which implements the [`Module`] trait (required). This can be any type, e.g. a
+///     path or an instantiation of a generic type like `MyModule<Config>`, see
+///     [Generic module types](#generic-module-types).
 ///   - `name`: ASCII string literal of the name of the kernel module (required).
 ///   - `authors`: array of ASCII string literals of the authors of the kernel module.
 ///   - `description`: string literal of the description of the kernel module.
 ///   - `license`: ASCII string literal of the license of the kernel module (required).
+///   - `license_files`: array of ASCII string literals of the paths of the license texts, relative
+///     to the root of the kernel tree, e.g. `"LICENSES/preferred/GPL-2.0"`. Each one is emitted as
+///     a `license_file` modinfo tag.
+///   - `license_note`: ASCII string literal of a free-form note about the licensing of the kernel
+///     module, e.g. additional rights granted, emitted as the `license_note` modinfo tag. Unlike
+///     `license`, it is not interpreted by the kernel, so it never affects whether the kernel is
+///     tainted.
 ///   - `alias`: array of ASCII string literals of the alias names of the kernel module.
+///   - `of_match`: array of ASCII string literals of the device tree compatibles the kernel module
+///     matches, of the form `"vendor,device"`. See [Device tree](#device-tree).
 ///   - `firmware`: array of ASCII string literals of the firmware files of
-///     the kernel module.
+///     the kernel module. Each file can only be listed once.
+///   - `firmware_modular_only`: boolean, whether `firmware` is only emitted for loadable modules
+///     (defaults to `false`).
+///   - `strict`: boolean, whether to reject configurations that are likely a mistake, currently
+///     `firmware` in a built-in kernel module (defaults to `false`).
+///   - `livepatch`: boolean, whether the kernel module is a livepatch, emitted as the `livepatch`
+///     modinfo tag like `MODULE_INFO(livepatch, "Y")` in C (defaults to `false`). Livepatch modules
+///     cannot be built-in, so building one into the kernel is a compile error, and they cannot be
+///     combined with `initcall_level`, `init_symbol`, `exit_symbol` or `test_stub`.
+///   - `scmversion`: ASCII string literal of the source control revision the kernel module was
+///     built from.
+///   - `abi_version`: ASCII string literal of the version of the kernel API the kernel module was
+///     written against, e.g. `"2.1"`, for out-of-tree modules. It may only contain ASCII
+///     alphanumerics, `.`, `_`, `+` and `-`. This is advisory: it is emitted as the `abi_version`
+///     modinfo tag for loaders and tooling to check, but the kernel does not check it, and there is
+///     no kernel constant to verify it against at compile-time.
+///   - `compression`: ASCII string literal of the compression the kernel module is expected to be
+///     installed with, one of `"none"`, `"gzip"`, `"xz"` and `"zstd"`, emitted as the
+///     `compression` modinfo tag for loadable modules. This is a hint for tooling, the module is
+///     not compressed by it.
+///   - `info`: map of additional modinfo tags to ASCII string literals or booleans, see
+///     [Additional modinfo tags](#additional-modinfo-tags).
+///   - `module_type`: ASCII string literal of the class of the kernel module, e.g. `"net"` or
+///     `"block"`, emitted as the `type` modinfo tag. It may only contain lowercase letters, digits
+///     and `_`. This is not a standard tag, it is meant for tooling that classifies modules.
+///   - `initcall_level`: ASCII string literal of the initcall level used when the kernel module is
+///     built-in, named like the C `*_initcall` macros without the suffix, e.g. `"late"` for
+///     `late_initcall` (defaults to `"device"`).
+///   - `init_symbol`: ASCII string literal of the C identifier of the init function used when the
+///     kernel module is built-in (defaults to `__<name>_init`).
+///   - `exit_symbol`: ASCII string literal of the C identifier of the exit function used when the
+///     kernel module is built-in (defaults to `__<name>_exit`).
+///   - `data_section`: ASCII string literal of the linker section of the static holding the
+///     instance of the kernel module type, see [Data section](#data-section). It may only contain
+///     ASCII alphanumerics, `.`, `_` and `$`.
+///   - `test_stub`: boolean, whether to generate a minimal stub for host tests instead of the
+///     kernel module glue, see [Test stubs](#test-stubs) (defaults to `false`).
+///
+/// `init_symbol` and `exit_symbol` are only needed for architectures whose linker scripts or boot
+/// code expect the init and exit functions of built-in modules under specific names. Loadable
+/// modules always use `init_module` and `cleanup_module`, like C modules do.
 #[proc_macro]
 pub fn module(ts: TokenStream) -> TokenStream {
     module::module(ts)
@@ -169,350 +542,2644 @@ pub fn module(ts: TokenStream) -> TokenStream {
 /// assert_eq!(<Foo as Operations>::HAS_BAR, false);
 /// ```
 ///
-/// [`kernel::error::VTABLE_DEFAULT_ERROR`]: ../kernel/error/constant.VTABLE_DEFAULT_ERROR.html
-#[proc_macro_attribute]
-pub fn vtable(attr: TokenStream, ts: TokenStream) -> TokenStream {
-    vtable::vtable(attr, ts)
-}
-
-/// Export a function so that C code can call it via a header file.
-///
-/// Functions exported using this macro can be called from C code using the declaration in the
-/// appropriate header file. It should only be used in cases where C calls the function through a
-/// header file; cases where C calls into Rust via a function pointer in a vtable (such as
-/// `file_operations`) should not use this macro.
+/// All items generated by this macro are associated items of the trait, or of the implementing
+/// type, and never free items. Hence implementations of the same trait for different types each
+/// have their own `HAS_*` constants, which do not conflict with each other:
 ///
-/// This macro has the following effect:
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
 ///
-/// * Disables name mangling for this function.
-/// * Verifies at compile-time that the function signature matches the declaration in the header
-///   file.
+/// #[vtable]
+/// pub trait Operations {
+///     fn foo(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
 ///
-/// You must declare the signature of the Rust function in a header file that is included by
-/// `rust/bindings/bindings_helper.h`.
+///     fn bar(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
 ///
-/// This macro is *not* the same as the C macros `EXPORT_SYMBOL_*`. All Rust symbols are currently
-/// automatically exported with `EXPORT_SYMBOL_GPL`.
-#[proc_macro_attribute]
-pub fn export(attr: TokenStream, ts: TokenStream) -> TokenStream {
-    export::export(attr, ts)
-}
-
-/// Concatenate two identifiers.
+/// struct Foo;
+/// struct Bar;
 ///
-/// This is useful in macros that need to declare or reference items with names
+/// #[vtable]
+/// impl Operations for Foo {
+///     fn foo(&self) -> Result<()> {
+/// #        Err(EINVAL)
+///         // ...
+///     }
+/// }
+///
+/// #[vtable]
+/// impl Operations for Bar {
+///     fn bar(&self) -> Result<()> {
+/// #        Err(EINVAL)
+///         // ...
+///     }
+/// }
+///
+/// assert_eq!((Foo::HAS_FOO, Foo::HAS_BAR), (true, false));
+/// assert_eq!((Bar::HAS_FOO, Bar::HAS_BAR), (false, true));
+/// ```
+///
+/// The trait may be generic, including over const parameters. The `HAS_*` constants are
+/// associated items of the trait, so they are generated for every instantiation:
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable]
+/// pub trait QueueOperations<const DEPTH: usize>: Sized {
+///     fn submit(&self, _tags: [u32; DEPTH]) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+///
+///     fn flush(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct Queue;
+///
+/// #[vtable]
+/// impl QueueOperations<32> for Queue {
+///     fn submit(&self, _tags: [u32; 32]) -> Result<()> {
+/// #        Err(EINVAL)
+///         // ...
+///     }
+/// }
+///
+/// assert_eq!(<Queue as QueueOperations<32>>::HAS_SUBMIT, true);
+/// assert_eq!(<Queue as QueueOperations<32>>::HAS_FLUSH, false);
+/// ```
+///
+/// # Optional methods
+///
+/// If most methods are required, the optional ones can be listed with
+/// `#[vtable(optional(...))]` on the trait. All other methods must not have a default
+/// implementation, and their `HAS_*` constants are hidden and always `true`. Constants that are
+/// declared explicitly in the trait are kept as they are, as for any `#[vtable]` trait.
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable(optional(ioctl))]
+/// pub trait DeviceOperations: Sized {
+///     fn open(&self) -> Result<()>;
+///
+///     fn release(&self);
+///
+///     fn ioctl(&self, _cmd: u32) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct Device;
+///
+/// #[vtable]
+/// impl DeviceOperations for Device {
+///     fn open(&self) -> Result<()> {
+///         Ok(())
+///     }
+///
+///     fn release(&self) {}
+/// }
+///
+/// assert_eq!(<Device as DeviceOperations>::HAS_IOCTL, false);
+/// ```
+///
+/// # Completeness
+///
+/// The trait also gets a `VTABLE_IS_COMPLETE` constant that is true only if every method is
+/// implemented. Code for which a `NULL` entry would be a bug can check it at compile-time:
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable]
+/// pub trait Operations {
+///     fn foo(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+///
+///     fn bar(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct Partial;
+///
+/// #[vtable]
+/// impl Operations for Partial {
+///     fn foo(&self) -> Result<()> {
+/// #        Err(EINVAL)
+///         // ...
+///     }
+/// }
+///
+/// struct Complete;
+///
+/// #[vtable]
+/// impl Operations for Complete {
+///     fn foo(&self) -> Result<()> {
+/// #        Err(EINVAL)
+///         // ...
+///     }
+///
+///     fn bar(&self) -> Result<()> {
+/// #        Err(EINVAL)
+///         // ...
+///     }
+/// }
+///
+/// const _: () = assert!(Complete::VTABLE_IS_COMPLETE);
+/// assert!(!Partial::VTABLE_IS_COMPLETE);
+/// ```
+///
+/// `VTABLE_ASSERT_COMPLETE` is a constant that fails to compile when it is evaluated for an
+/// incomplete implementation. It can be used where a type is handed to C, so that a `NULL` entry
+/// is rejected at compile-time instead of being dereferenced at runtime:
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable]
+/// pub trait Operations {
+///     fn foo(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// fn register<T: Operations>() {
+///     let () = T::VTABLE_ASSERT_COMPLETE;
+///     // Pass the vtable of `T` to C.
+/// }
+///
+/// struct Complete;
+///
+/// #[vtable]
+/// impl Operations for Complete {
+///     fn foo(&self) -> Result<()> {
+/// #        Err(EINVAL)
+///         // ...
+///     }
+/// }
+///
+/// const _: () = Complete::VTABLE_ASSERT_COMPLETE;
+/// register::<Complete>();
+/// ```
+///
+/// ```compile_fail
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable]
+/// pub trait Operations {
+///     fn foo(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct Partial;
+///
+/// #[vtable]
+/// impl Operations for Partial {}
+///
+/// // error: not every method of the vtable is implemented
+/// const _: () = Partial::VTABLE_ASSERT_COMPLETE;
+/// ```
+///
+/// # Reflection
+///
+/// With `#[vtable(reflect)]` on the trait, an associated function `method_names()` is generated
+/// that returns the names of the methods an implementation overrides, e.g. for tracing:
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable(reflect)]
+/// pub trait Operations: Sized {
+///     fn foo(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+///
+///     fn bar(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct Foo;
+///
+/// #[vtable]
+/// impl Operations for Foo {
+///     fn bar(&self) -> Result<()> {
+/// #        Err(EINVAL)
+///         // ...
+///     }
+/// }
+///
+/// assert_eq!(Foo::method_names(), ["bar"]);
+/// ```
+///
+/// It also generates a constant `LIVE_METHOD_COUNT` with the number of entries of the vtable
+/// that are not NULL, including the required methods:
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable(reflect)]
+/// pub trait Operations: Sized {
+///     fn open(&self) -> Result<()>;
+///
+///     fn read(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+///
+///     fn write(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+///
+///     fn ioctl(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct ReadOnly;
+///
+/// #[vtable]
+/// impl Operations for ReadOnly {
+///     fn open(&self) -> Result<()> {
+/// #        Ok(())
+///         // ...
+///     }
+///
+///     fn read(&self) -> Result<()> {
+/// #        Ok(())
+///         // ...
+///     }
+/// }
+///
+/// const _: () = assert!(ReadOnly::LIVE_METHOD_COUNT == 2);
+/// ```
+///
+/// # Scaffolding
+///
+/// With `#[vtable(scaffold)]` on the trait, a constant `VTABLE_REQUIRED_METHODS` is generated that
+/// lists the signatures of the methods without a default implementation, i.e. those every
+/// implementation has to provide. Its documentation contains a skeleton implementation with these
+/// methods, which can be copied when starting a new driver:
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable(scaffold)]
+/// pub trait Operations {
+///     fn open(&self) -> Result<()>;
+///
+///     unsafe fn release(&self, data: *mut u8);
+///
+///     fn ioctl(&self, cmd: u32) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct Foo;
+///
+/// #[vtable]
+/// impl Operations for Foo {
+///     fn open(&self) -> Result<()> {
+/// #        Ok(())
+///         // ...
+///     }
+///
+///     unsafe fn release(&self, _data: *mut u8) {
+///         // ...
+///     }
+/// }
+///
+/// let required = Foo::VTABLE_REQUIRED_METHODS;
+/// assert_eq!(required.len(), 2);
+/// assert!(required[0].starts_with("fn open"));
+/// assert!(required[1].starts_with("unsafe fn release"));
+/// ```
+///
+/// # C vtable fields
+///
+/// With `#[vtable(fields(...))]` on an impl block, a `VTABLE_FIELDS` constant is generated on the
+/// implementing type. It is a tuple with one `Option` of a function pointer per field of the C
+/// vtable, in the order of the `fields` argument, that is `Some` exactly if the method of the same
+/// name is implemented. Each field is given as `name: Type = value`, where `Type` is the function
+/// pointer type of the C field and `value` the function stored in it, e.g. an adapter that calls
+/// the method:
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable]
+/// pub trait Operations: Sized {
+///     fn open(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+///
+///     fn release(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// type Callback = unsafe extern "C" fn(data: *mut u8) -> i32;
+///
+/// unsafe extern "C" fn open_callback<T: Operations>(_data: *mut u8) -> i32 {
+///     // ...
+/// #   0
+/// }
+///
+/// unsafe extern "C" fn release_callback<T: Operations>(_data: *mut u8) -> i32 {
+///     // ...
+/// #   0
+/// }
+///
+/// struct Foo;
+///
+/// #[vtable(fields(
+///     open: Callback = open_callback::<Self>,
+///     release: Callback = release_callback::<Self>,
+/// ))]
+/// impl Operations for Foo {
+///     fn open(&self) -> Result<()> {
+/// #        Ok(())
+///         // ...
+///     }
+/// }
+///
+/// let (open, release) = Foo::VTABLE_FIELDS;
+/// assert!(open.is_some());
+/// assert!(release.is_none());
+/// ```
+///
+/// # Enforcing default implementations
+///
+/// With `#[vtable(enforce_default)]` on the trait, every default implementation must call
+/// `build_error!(VTABLE_DEFAULT_ERROR)`, so that the default can never be reached at runtime.
+/// Note that this is only a syntactic check: the call merely needs to appear somewhere in the
+/// body, it is not checked whether it is reachable or what the names resolve to.
+///
+/// ```compile_fail
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable(enforce_default)]
+/// pub trait Operations {
+///     fn foo(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+///
+///     // Error: the default implementation does not call `build_error!(VTABLE_DEFAULT_ERROR)`.
+///     fn bar(&self) -> Result<()> {
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// # C struct
+///
+/// With `#[vtable(c_type = "...")]` on the trait, the name of the C struct the vtable is built for
+/// is recorded in the constant `C_VTABLE_TYPE`, for readers and for tooling:
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable(c_type = "file_operations")]
+/// pub trait Operations {
+///     fn open(&self) -> Result<()>;
+///
+///     fn read(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct Foo;
+///
+/// #[vtable]
+/// impl Operations for Foo {
+///     fn open(&self) -> Result<()> {
+/// #        Ok(())
+///         // ...
+///     }
+/// }
+///
+/// assert_eq!(Foo::C_VTABLE_TYPE, "file_operations");
+/// ```
+///
+/// # Forwarding to references
+///
+/// With `#[vtable(forward_ref)]` on an impl block, the trait is also implemented for shared
+/// references to the type, forwarding every item to the implementation for the type itself. The
+/// `HAS_*` constants are forwarded as well, so both implementations have the same vtable.
+///
+/// This requires that:
+///
+/// - the references satisfy the supertraits of the trait, e.g. `&T` is only `Send` if `T` is
+///   `Sync`, and `&T` is never `'static`, unless the reference is,
+/// - all methods take `&self` or no receiver at all, since a shared reference cannot be turned into
+///   `T` or `&mut T`,
+/// - the parameters and return types of the methods do not use `Self`, since it is the reference
+///   in the forwarding implementation,
+/// - associated types are not generic.
+///
+/// ```
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable]
+/// pub trait Operations {
+///     fn open(&self, flags: u32) -> Result<u32>;
+///
+///     fn read(&self, _buf: &mut [u8]) -> Result<usize> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+///
+///     fn write(&self, _buf: &[u8]) -> Result<usize> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// struct Device;
+///
+/// #[vtable(forward_ref)]
+/// impl Operations for Device {
+///     fn open(&self, flags: u32) -> Result<u32> {
+///         Ok(flags)
+///     }
+///
+///     fn read(&self, buf: &mut [u8]) -> Result<usize> {
+/// #        Ok(buf.len())
+///         // ...
+///     }
+/// }
+///
+/// fn open<T: Operations>(ops: T) -> u32 {
+///     ops.open(1).unwrap_or(0)
+/// }
+///
+/// let device = Device;
+/// assert_eq!(open(&device), 1);
+///
+/// const _: () = assert!(<&Device as Operations>::HAS_READ);
+/// const _: () = assert!(!<&Device as Operations>::HAS_WRITE);
+/// ```
+///
+/// # Lints
+///
+/// The generated items do not trigger any lints by themselves, so `#[vtable]` can be used in
+/// crates that deny warnings:
+///
+/// ```
+/// #![deny(warnings)]
+///
+/// use kernel::error::VTABLE_DEFAULT_ERROR;
+/// use kernel::prelude::*;
+///
+/// #[vtable(reflect)]
+/// pub trait Operations: Sized {
+///     fn open(&self) -> Result<()>;
+///
+///     fn ioctl(&self) -> Result<()> {
+///         build_error!(VTABLE_DEFAULT_ERROR)
+///     }
+/// }
+///
+/// pub struct Foo;
+///
+/// #[vtable]
+/// impl Operations for Foo {
+///     fn open(&self) -> Result<()> {
+/// #        Ok(())
+///         // ...
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// If a lint that is enabled in addition, e.g. from the `clippy::restriction` group, fires on the
+/// generated items nonetheless, it can be allowed on all of them with `#[vtable(allow(...))]`, on
+/// the trait as well as on the impl block:
+///
+/// ```ignore
+/// #[vtable(allow(clippy::missing_docs_in_private_items))]
+/// impl Operations for Foo {
+///     // ...
+/// }
+/// ```
+///
+/// [`kernel::error::VTABLE_DEFAULT_ERROR`]: ../kernel/error/constant.VTABLE_DEFAULT_ERROR.html
+#[proc_macro_attribute]
+pub fn vtable(attr: TokenStream, ts: TokenStream) -> TokenStream {
+    vtable::vtable(attr, ts)
+}
+
+/// Export a function so that C code can call it via a header file.
+///
+/// Functions exported using this macro can be called from C code using the declaration in the
+/// appropriate header file. It should only be used in cases where C calls the function through a
+/// header file; cases where C calls into Rust via a function pointer in a vtable (such as
+/// `file_operations`) should not use this macro.
+///
+/// This macro has the following effect:
+///
+/// * Disables name mangling for this function.
+/// * Verifies at compile-time that the function signature matches the declaration in the header
+///   file.
+///
+/// You must declare the signature of the Rust function in a header file that is included by
+/// `rust/bindings/bindings_helper.h`.
+///
+/// Exported functions need a single C symbol, so they must not be generic over types or
+/// constants. Lifetime parameters are allowed.
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: Exported functions must not be generic, since they need a single C symbol.
+/// #[export]
+/// pub unsafe extern "C" fn rust_fmt_argument<T>(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const T,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// Exported functions must not be `async` either, since C cannot poll the returned future. Export
+/// a blocking wrapper that runs the future to completion instead.
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: Exported functions must not be `async`, since C cannot poll the returned future.
+/// #[export]
+/// pub async unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// Types that are obviously not FFI-safe are rejected in the signature as well, even if the
+/// declaration in the header file was written to match them by mistake. This is a best-effort check
+/// for owned Rust types like `String` or `KVec<T>`, and for string slices, slices and trait objects,
+/// since pointers to these consist of more than one word:
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: Exported functions must only use FFI-safe types, but `String` is an owned Rust type
+/// // with an unspecified layout.
+/// #[export]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> String {
+///     String::new()
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::c_char;
+///
+/// // error: Exported functions must only use FFI-safe types, but `str` is not FFI-safe, a
+/// // reference to it consists of a pointer and a length.
+/// #[export]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: &str,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::alloc::KVec;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: Exported functions must only use FFI-safe types, but `KVec` is an owned Rust type
+/// // with an unspecified layout.
+/// #[export]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> KVec<u8> {
+///     KVec::new()
+/// }
+/// ```
+///
+/// `#[cfg(...)]` attributes of the function also apply to the generated items, so a conditionally
+/// compiled function is verified only when it is compiled:
+///
+/// ```ignore
+/// #[export]
+/// #[cfg(CONFIG_FOO)]
+/// pub unsafe extern "C" fn rust_foo(data: *mut c_void) -> c_int {
+///     // ...
+/// }
+/// ```
+///
+/// This macro is *not* the same as the C macros `EXPORT_SYMBOL_*`. All Rust symbols are currently
+/// automatically exported with `EXPORT_SYMBOL_GPL`.
+///
+/// # Modules
+///
+/// To export many functions at once, `#[export]` can be used on an inline module. Every function
+/// of the module that is declared `extern` is then exported as if it was annotated with the same
+/// `#[export(...)]`, including the verification of its signature. Other items, e.g. helper
+/// functions with the Rust ABI, are kept as they are, and functions that have an `#[export]`
+/// attribute of their own are exported with it instead.
+///
+/// ```ignore
+/// // C header:
+/// // int rust_get_value(void);
+/// // void rust_set_value(int value);
+/// #[export]
+/// mod ffi {
+///     use kernel::ffi::c_int;
+///
+///     fn clamp(value: c_int) -> c_int {
+///         value.clamp(0, 100)
+///     }
+///
+///     pub extern "C" fn rust_get_value() -> c_int {
+///         // ...
+///     }
+///
+///     pub extern "C" fn rust_set_value(value: c_int) {
+///         let value = clamp(value);
+///         // ...
+///     }
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// #[export]
+/// mod ffi {
+///     use kernel::ffi::{c_char, c_void};
+///
+///     // Not exported, hence not checked.
+///     fn name() -> String {
+///         String::new()
+///     }
+///
+///     // error: Exported functions must only use FFI-safe types
+///     pub unsafe extern "C" fn rust_fmt_argument(
+///         buf: *mut c_char,
+///         end: *mut c_char,
+///         ptr: *const c_void,
+///     ) -> String {
+///         name()
+///     }
+/// }
+/// ```
+///
+/// # Registration
+///
+/// Some functions are not called by name, but discovered by C through a table in a linker
+/// section, similar to initcalls. With `#[export(register = "section")]`, a pointer to the
+/// function is placed in the given linker section (which may only contain ASCII alphanumerics,
+/// `.`, `_` and `$`).
+///
+/// ```ignore
+/// #[export(register = ".rust_callbacks")]
+/// pub unsafe extern "C" fn rust_callback(data: *mut c_void) -> c_int {
+///     // ...
+/// }
+/// ```
+///
+/// The function may have lifetime parameters and a `where` clause, the type of the pointer binds
+/// the lifetimes with `for<...>`:
+///
+/// ```
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// #[export(register = ".rust_callbacks")]
+/// pub unsafe extern "C" fn rust_fmt_argument<'a>(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char
+/// where
+///     c_char: 'a,
+/// {
+///     // SAFETY: The caller passes a valid buffer.
+///     let _first: Option<&'a mut c_char> = unsafe { buf.as_mut() };
+///     buf
+/// }
+/// ```
+///
+/// # Sections
+///
+/// Like C functions annotated with `__init`, exported functions that are only needed during boot
+/// can be placed in a section that is discarded afterwards with `#[export(section = "section")]`.
+/// The section name is validated like the one of `register`, and the signature is verified as
+/// usual. As in C, such functions must not be called after the section has been freed.
+///
+/// ```ignore
+/// #[export(section = ".init.text")]
+/// pub unsafe extern "C" fn rust_early_setup(data: *mut c_void) -> c_int {
+///     // ...
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: Invalid section name ".init text".
+/// #[export(section = ".init text")]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// # Verification in tests
+///
+/// With `#[export(test_sig)]`, the signature is additionally verified in a `#[test]` function
+/// named `__export_test_sig_<name>`, e.g. for CI jobs that track the FFI boundary in their test
+/// results. The compile-time check is kept, since kernel code is not built with `cfg(test)`:
+///
+/// ```
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// #[export(test_sig)]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf
+/// }
+///
+/// // Generated in addition to the compile-time check:
+/// // #[cfg(test)]
+/// // #[test]
+/// // fn __export_test_sig_rust_fmt_argument() { ... }
+/// ```
+///
+/// A mismatch is still a compile error:
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: the signature does not match the declaration of `rust_fmt_argument`
+/// #[export(test_sig)]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// # Symbol versions
+///
+/// With `CONFIG_MODVERSIONS`, modules are checked against a CRC of the signature of every symbol
+/// they import. Nothing has to be done for this in `#[export]`: the CRCs of Rust exports are
+/// computed from their debug information by `gendwarfksyms` (`CONFIG_GENDWARFKSYMS`) when the
+/// object is built, and modpost emits the `__crc_<name>` entries for them like for C exports.
+///
+/// # Calling conventions
+///
+/// Exported functions use the `C` calling convention by default. Functions declared with another
+/// calling convention in the header file, e.g. `__attribute__((sysv_abi))`, can be exported with
+/// `#[export(abi = "...")]`, where the ABI is one of `"C"`, `"C-unwind"`, `"system"`, `"sysv64"`,
+/// `"win64"`, `"efiapi"` and `"aapcs"`. The function has to be declared `extern` with the same ABI:
+///
+/// ```
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// #[export(abi = "C")]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: `rust_fmt_argument` must be declared `extern "C-unwind"`
+/// #[export(abi = "C-unwind")]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// The ABI is part of the signature that is verified, so it also has to match the header file.
+/// `rust_fmt_argument` is declared with the `C` calling convention:
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: the signature does not match the declaration of `rust_fmt_argument`
+/// #[export(abi = "C-unwind")]
+/// pub unsafe extern "C-unwind" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// Other ABIs are rejected, even if they are supported by the compiler:
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// // error: Unsupported ABI "system-unwind".
+/// #[export(abi = "system-unwind")]
+/// pub unsafe extern "system-unwind" fn rust_fmt_argument(
+///     buf: *mut c_char,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf
+/// }
+/// ```
+///
+/// # Function pointer parameters
+///
+/// Parameters that are C function pointers (e.g. callbacks passed to registration functions) are
+/// verified like any other parameter, so they must be spelled the way `bindgen` generates them:
+/// `unsafe extern "C" fn(...)`, wrapped in an `Option` since the C pointer may be `NULL`. A safe
+/// `extern "C" fn(...)` parameter does not match the declaration and is rejected at compile-time.
+///
+/// For example, `call_rcu()` takes a callback, with the C declaration
+/// `void call_rcu(struct rcu_head *head, rcu_callback_t func);`:
+///
+/// ```
+/// # use kernel::macros::export;
+/// use kernel::bindings::callback_head;
+///
+/// #[export]
+/// pub unsafe extern "C" fn call_rcu(
+///     head: *mut callback_head,
+///     func: Option<unsafe extern "C" fn(head: *mut callback_head)>,
+/// ) {
+///     // ...
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// use kernel::bindings::callback_head;
+///
+/// // error: the signature does not match the declaration of `call_rcu`
+/// #[export]
+/// pub unsafe extern "C" fn call_rcu(
+///     head: *mut callback_head,
+///     func: Option<extern "C" fn(head: *mut callback_head)>,
+/// ) {
+///     // ...
+/// }
+/// ```
+///
+/// # Transparent wrappers
+///
+/// Parameters and return values can use `#[repr(transparent)]` wrappers of the C types, e.g. a
+/// newtype for file descriptors where the header declares an `int`. The wrappers and their inner
+/// types are listed with `#[export(transparent(Wrapper = Inner, ...))]` and the signature is
+/// verified with the wrappers replaced by their inner types. Each wrapper needs to be marked with
+/// `#[export]` as well, which checks that it is `#[repr(transparent)]`, not generic and has exactly
+/// one field of the inner type.
+///
+/// ```ignore
+/// #[export]
+/// #[repr(transparent)]
+/// pub struct Fd(c_int);
+///
+/// // C header: int rust_dup_fd(int fd);
+/// #[export(transparent(Fd = c_int))]
+/// pub unsafe extern "C" fn rust_dup_fd(fd: Fd) -> Fd {
+///     // ...
+/// }
+/// ```
+///
+/// Wrappers can be used for pointers as well:
+///
+/// ```
+/// # use kernel::macros::export;
+/// use kernel::ffi::{c_char, c_void};
+///
+/// #[export]
+/// #[repr(transparent)]
+/// pub struct Buf(*mut c_char);
+///
+/// #[export(transparent(Buf = *mut c_char))]
+/// pub unsafe extern "C" fn rust_fmt_argument(
+///     buf: Buf,
+///     end: *mut c_char,
+///     ptr: *const c_void,
+/// ) -> *mut c_char {
+///     buf.0
+/// }
+/// ```
+///
+/// A wrapper that is not `#[repr(transparent)]` is rejected, since its layout is not guaranteed to
+/// be the one of the inner type:
+///
+/// ```compile_fail
+/// # use kernel::macros::export;
+/// #[export]
+/// pub struct Fd(i32);
+/// ```
+#[proc_macro_attribute]
+pub fn export(attr: TokenStream, ts: TokenStream) -> TokenStream {
+    export::export(attr, ts)
+}
+
+/// Concatenate two identifiers.
+///
+/// This is useful in macros that need to declare or reference items with names
 /// starting with a fixed prefix and ending in a user specified name. The resulting
 /// identifier has the span of the second argument.
 ///
-/// # Examples
+/// # Examples
+///
+/// ```
+/// # const binder_driver_return_protocol_BR_OK: u32 = 0;
+/// # const binder_driver_return_protocol_BR_ERROR: u32 = 1;
+/// # const binder_driver_return_protocol_BR_TRANSACTION: u32 = 2;
+/// # const binder_driver_return_protocol_BR_REPLY: u32 = 3;
+/// # const binder_driver_return_protocol_BR_DEAD_REPLY: u32 = 4;
+/// # const binder_driver_return_protocol_BR_TRANSACTION_COMPLETE: u32 = 5;
+/// # const binder_driver_return_protocol_BR_INCREFS: u32 = 6;
+/// # const binder_driver_return_protocol_BR_ACQUIRE: u32 = 7;
+/// # const binder_driver_return_protocol_BR_RELEASE: u32 = 8;
+/// # const binder_driver_return_protocol_BR_DECREFS: u32 = 9;
+/// # const binder_driver_return_protocol_BR_NOOP: u32 = 10;
+/// # const binder_driver_return_protocol_BR_SPAWN_LOOPER: u32 = 11;
+/// # const binder_driver_return_protocol_BR_DEAD_BINDER: u32 = 12;
+/// # const binder_driver_return_protocol_BR_CLEAR_DEATH_NOTIFICATION_DONE: u32 = 13;
+/// # const binder_driver_return_protocol_BR_FAILED_REPLY: u32 = 14;
+/// use kernel::macros::concat_idents;
+///
+/// macro_rules! pub_no_prefix {
+///     ($prefix:ident, $($newname:ident),+) => {
+///         $(pub(crate) const $newname: u32 = concat_idents!($prefix, $newname);)+
+///     };
+/// }
+///
+/// pub_no_prefix!(
+///     binder_driver_return_protocol_,
+///     BR_OK,
+///     BR_ERROR,
+///     BR_TRANSACTION,
+///     BR_REPLY,
+///     BR_DEAD_REPLY,
+///     BR_TRANSACTION_COMPLETE,
+///     BR_INCREFS,
+///     BR_ACQUIRE,
+///     BR_RELEASE,
+///     BR_DECREFS,
+///     BR_NOOP,
+///     BR_SPAWN_LOOPER,
+///     BR_DEAD_BINDER,
+///     BR_CLEAR_DEATH_NOTIFICATION_DONE,
+///     BR_FAILED_REPLY
+/// );
+///
+/// assert_eq!(BR_OK, binder_driver_return_protocol_BR_OK);
+/// ```
+///
+/// Keywords are concatenated by their text. If the result is a keyword itself, it is emitted as a
+/// raw identifier:
+///
+/// ```
+/// use kernel::macros::concat_idents;
+///
+/// fn mut_accessor() -> u32 {
+///     1
+/// }
+///
+/// fn r#type() -> u32 {
+///     2
+/// }
+///
+/// assert_eq!(concat_idents!(mut, _accessor)(), 1);
+/// assert_eq!(concat_idents!(ty, pe)(), 2);
+/// ```
+///
+/// Segments can also be decimal integer literals without suffix, e.g. to build names with a
+/// trailing number. If both segments are integer literals, the result is an integer literal as
+/// well, so it can be used where a number is expected:
+///
+/// ```
+/// use kernel::macros::concat_idents;
+///
+/// const REG1: u32 = 0x10;
+///
+/// macro_rules! reg {
+///     ($bank:literal, $index:literal) => {
+///         (concat_idents!(REG, $bank), concat_idents!($bank, $index))
+///     };
+/// }
+///
+/// assert_eq!(reg!(1, 5), (0x10, 15));
+/// ```
+///
+/// A segment can also be a path, e.g. a `$name:path` fragment. Only its last component is used,
+/// the rest of the path is dropped, so the result names an item in the scope of the invocation:
+///
+/// ```
+/// use kernel::macros::concat_idents;
+///
+/// mod regs {
+///     pub(crate) const STATUS: u32 = 4;
+/// }
+///
+/// const STATUS_MASK: u32 = 0xf;
+///
+/// macro_rules! mask {
+///     ($reg:path) => {
+///         concat_idents!($reg, _MASK)
+///     };
+/// }
+///
+/// assert_eq!(mask!(regs::STATUS), 0xf);
+/// assert_eq!(concat_idents!(regs::STATUS, _MASK), 0xf);
+/// assert_eq!(regs::STATUS, 4);
+/// ```
+///
+/// A segment can also be a nested invocation of `concat_idents!` or [`concat_idents_spanned!`],
+/// which is expanded first. This concatenates more than two segments:
+///
+/// ```
+/// use kernel::macros::concat_idents;
+///
+/// const REG_STATUS_MASK: u32 = 0xf;
+/// const REG_STATUS_MASK_HI: u32 = 0xf0;
+///
+/// assert_eq!(concat_idents!(concat_idents!(REG_, STATUS), _MASK), 0xf);
+/// assert_eq!(
+///     concat_idents!(concat_idents!(concat_idents!(REG_, STATUS), _MASK), _HI),
+///     0xf0
+/// );
+/// assert_eq!(concat_idents!(1, kernel::macros::concat_idents!(2, 3)), 123);
+/// ```
+///
+/// Lifetimes cannot be concatenated. A macro can only be invoked where an item, a statement, an
+/// expression, a pattern or a type is expected, so a lifetime it produced could not be used in any
+/// position. Lifetimes that need to be unique can be passed to the generating macro as a
+/// `$lt:lifetime` fragment instead.
+///
+/// ```compile_fail
+/// use kernel::macros::concat_idents;
+///
+/// // error: Lifetimes cannot be concatenated
+/// let _ = concat_idents!('a, _region);
+/// ```
+///
+/// Macro invocations cannot be used as a segment of a path, so e.g.
+/// `self::concat_idents!(a, b)::CONST` does not parse. Use [`paste!`] to build path segments
+/// instead:
+///
+/// ```
+/// mod binder_driver_return_protocol {
+///     pub(crate) const BR_OK: u32 = 0;
+/// }
+///
+/// macro_rules! lookup {
+///     ($prefix:ident, $suffix:ident, $name:ident) => {
+///         kernel::macros::paste! {
+///             [<$prefix $suffix>]::$name
+///         }
+///     };
+/// }
+///
+/// assert_eq!(lookup!(binder_driver_, return_protocol, BR_OK), 0);
+/// ```
+///
+/// For the same reason, `concat_idents!` cannot name an item that is being defined, e.g. a
+/// function. [`paste!`] can, and attributes such as documentation are attached to the item as
+/// usual:
+///
+/// ```
+/// macro_rules! define_getter {
+///     ($field:ident, $doc:literal) => {
+///         kernel::macros::paste! {
+///             #[doc = $doc]
+///             fn [<get_ $field>]() -> u32 {
+///                 0
+///             }
+///         }
+///     };
+/// }
+///
+/// define_getter!(status, "Returns the status register.");
+///
+/// assert_eq!(get_status(), 0);
+/// ```
+///
+/// [`stringify!`] does not expand macros in its input, so `stringify!(concat_idents!(a, b))` is
+/// `"concat_idents!(a, b)"` rather than `"ab"`. To get the concatenated name as a string, stringify
+/// the segments and join them with [`concat!`] instead:
+///
+/// ```
+/// use kernel::macros::concat_idents;
+///
+/// macro_rules! named {
+///     ($prefix:ident, $name:ident) => {
+///         (concat_idents!($prefix, $name), concat!(stringify!($prefix), stringify!($name)))
+///     };
+/// }
+///
+/// const REG_STATUS: u32 = 4;
+///
+/// assert_eq!(named!(REG_, STATUS), (4, "REG_STATUS"));
+/// assert_eq!(stringify!(concat_idents!(REG_, STATUS)), "concat_idents!(REG_, STATUS)");
+/// ```
+///
+/// [`concat_idents_spanned!`]: macro@concat_idents_spanned
+/// [`paste!`]: macro@paste
+#[proc_macro]
+pub fn concat_idents(ts: TokenStream) -> TokenStream {
+    concat_idents::concat_idents(ts)
+}
+
+/// Concatenate identifiers, with the span of the given token.
+///
+/// Like [`concat_idents!`], but the first argument is a token whose span is used for the
+/// resulting identifier, followed by a `;` and the segments to concatenate. Any number of segments
+/// can be given.
+///
+/// The span determines where diagnostics about the identifier point, and, for identifiers
+/// introduced by a macro, from where they can be named.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::macros::concat_idents_spanned;
+///
+/// macro_rules! define_counter {
+///     ($anchor:ident, $name:ident) => {
+///         // The local variable has the span of `$anchor`, so the caller can name it.
+///         let concat_idents_spanned!($anchor; counter_, $name, _total) = 0u32;
+///     };
+/// }
+///
+/// define_counter!(here, irq);
+///
+/// assert_eq!(counter_irq_total, 0);
+/// ```
+///
+/// [`concat_idents!`]: macro@concat_idents
+#[proc_macro]
+pub fn concat_idents_spanned(ts: TokenStream) -> TokenStream {
+    concat_idents::concat_idents_spanned(ts)
+}
+
+/// Used to specify the pinning information of the fields of a struct.
+///
+/// This is somewhat similar in purpose as
+/// [pin-project-lite](https://crates.io/crates/pin-project-lite).
+/// Place this macro on a struct definition and then `#[pin]` in front of the attributes of each
+/// field you want to structurally pin.
+///
+/// This macro enables the use of the [`pin_init!`] macro. When pin-initializing a `struct`,
+/// then `#[pin]` directs the type of initializer that is required.
+///
+/// If your `struct` implements `Drop`, then you need to add `PinnedDrop` as arguments to this
+/// macro, and change your `Drop` implementation to `PinnedDrop` annotated with
+/// `#[`[`macro@pinned_drop`]`]`, since dropping pinned values requires extra care.
+///
+/// Smart pointers like `KBox<T>` or `Arc<T>` do not structurally pin their contents, so `#[pin]`
+/// is rejected on fields of these types. To keep large pinned state in a separate allocation, use
+/// a `Pin<KBox<T>>` field without `#[pin]` instead; it can be projected to `Pin<&mut T>` with
+/// [`Pin::as_mut`]. This also applies to pinned trait objects, e.g. `Pin<KBox<dyn Trait>>`.
+/// The error names the smart pointer that was found.
+///
+/// Any other type can be used as a `#[pin]` field, including wrappers defined by the user, e.g.
+/// `#[pin] queue: Guarded<Mutex<T>>`. The field is initialized by an initializer of the wrapper,
+/// and projects to `Pin<&mut Guarded<Mutex<T>>>`. Projecting further to the wrapped value is up to
+/// the wrapper, which can use `#[pin_data]` itself to structurally pin it.
+///
+/// `#[pin]` fields may be wrapped in `ManuallyDrop<T>` to control the order in which they are
+/// torn down. Since pinned values must be dropped before their memory is reused, such fields
+/// must then be dropped in place in the `PinnedDrop` implementation.
+///
+/// Attributes on the fields other than `#[pin]`, including doc comments, are kept on the fields
+/// of the struct, so pinned fields are documented like any other field.
+///
+/// The last field may be unsized, e.g. a trailing `[T]` for a flexible array, and it may be
+/// `#[pin]` as well. Its projection is a `Pin<&mut [T]>`. Like any struct with an unsized field,
+/// such a struct cannot be created directly, only by an unsizing coercion: make the struct generic
+/// over the type of the last field with a `?Sized` bound, initialize it with an array, e.g. in a
+/// `Pin<KBox<S<[T; N]>>>`, and coerce the pointer to a `Pin<KBox<S<[T]>>>` afterwards. The
+/// coercion does not move the value, so the pinning guarantee of the array carries over to the
+/// slice.
+///
+/// Attributes on the struct, including `#[repr(...)]`, are kept as they are. The projections
+/// generated by this macro are separate types, so they do not affect the layout of the struct.
+///
+/// This macro does not implement `Send` or `Sync`, so the struct implements them exactly if all of
+/// its fields do, whether they are pinned or not. The helper types generated by this macro do not
+/// contain the fields, so they cannot be used to move values across threads either.
+///
+/// # Examples
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::{sync::Mutex, process::Command};
+/// # use kernel::macros::pin_data;
+/// #[pin_data]
+/// struct DriverData {
+///     #[pin]
+///     queue: Mutex<KVec<Command>>,
+///     buf: KBox<[u8; 1024 * 1024]>,
+/// }
+/// ```
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::{sync::Mutex, process::Command};
+/// # use core::pin::Pin;
+/// # pub struct Info;
+/// # mod bindings {
+/// #     pub unsafe fn destroy_info(_ptr: *mut super::Info) {}
+/// # }
+/// use kernel::macros::{pin_data, pinned_drop};
+///
+/// #[pin_data(PinnedDrop)]
+/// struct DriverData {
+///     #[pin]
+///     queue: Mutex<KVec<Command>>,
+///     buf: KBox<[u8; 1024 * 1024]>,
+///     raw_info: *mut Info,
+/// }
+///
+/// #[pinned_drop]
+/// impl PinnedDrop for DriverData {
+///     fn drop(self: Pin<&mut Self>) {
+///         unsafe { bindings::destroy_info(self.raw_info) };
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::{sync::Mutex, process::Command};
+/// # use core::pin::Pin;
+/// # use kernel::macros::pin_data;
+/// #[pin_data]
+/// struct DriverData {
+///     #[pin]
+///     queue: Mutex<KVec<Command>>,
+///     state: Pin<KBox<Mutex<[u8; 1024 * 1024]>>>,
+/// }
+///
+/// impl DriverData {
+///     fn state(&mut self) -> Pin<&mut Mutex<[u8; 1024 * 1024]>> {
+///         self.state.as_mut()
+///     }
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::sync::Mutex;
+/// # use kernel::macros::pin_data;
+/// #[pin_data]
+/// struct Shared<T> {
+///     // error: `#[pin]` does not pin the contents of a `KBox`, use a `Pin<KBox<T>>` field
+///     // without `#[pin]` instead.
+///     #[pin]
+///     inner: KBox<Mutex<T>>,
+/// }
+/// ```
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::sync::Mutex;
+/// # use core::pin::Pin;
+/// # use kernel::macros::pin_data;
+/// trait Operations {
+///     fn run(self: Pin<&mut Self>);
+/// }
+///
+/// #[pin_data]
+/// struct Device {
+///     #[pin]
+///     lock: Mutex<()>,
+///     ops: Pin<KBox<dyn Operations>>,
+/// }
+///
+/// impl Device {
+///     fn run(&mut self) {
+///         let ops: Pin<&mut dyn Operations> = self.ops.as_mut();
+///         ops.run();
+///     }
+/// }
+/// ```
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use core::pin::Pin;
+/// # use kernel::macros::pin_data;
+/// #[pin_data]
+/// struct S<T> {
+///     head: u32,
+///     #[pin]
+///     tail: [T],
+/// }
+///
+/// #[pin_data]
+/// struct Table<Tail: ?Sized> {
+///     len: u32,
+///     #[pin]
+///     entries: Tail,
+/// }
+///
+/// fn unsize(table: Pin<KBox<Table<[u64; 4]>>>) -> Pin<KBox<Table<[u64]>>> {
+///     table
+/// }
+/// ```
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::sync::Mutex;
+/// # use kernel::macros::pin_data;
+/// /// Fails to compile if the doc comments of the fields below are lost.
+/// #[deny(missing_docs)]
+/// pub mod device {
+///     use super::*;
+///
+///     /// A device.
+///     #[pin_data]
+///     pub struct Device {
+///         /// Protects the state of the device.
+///         #[pin]
+///         pub lock: Mutex<u32>,
+///         /// The number of the device.
+///         pub number: u32,
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::sync::Mutex;
+/// # use core::mem::{align_of, size_of};
+/// # use kernel::macros::pin_data;
+/// #[pin_data]
+/// #[repr(C)]
+/// struct Registers {
+///     ctrl: u32,
+///     #[pin]
+///     lock: Mutex<u64>,
+///     status: u8,
+/// }
+///
+/// #[repr(C)]
+/// struct PlainRegisters {
+///     ctrl: u32,
+///     lock: Mutex<u64>,
+///     status: u8,
+/// }
+///
+/// assert_eq!(size_of::<Registers>(), size_of::<PlainRegisters>());
+/// assert_eq!(align_of::<Registers>(), align_of::<PlainRegisters>());
+/// ```
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::{sync::Mutex, process::Command};
+/// # use core::pin::Pin;
+/// # use kernel::macros::pin_data;
+/// #[pin_data]
+/// struct Guarded<T> {
+///     #[pin]
+///     inner: T,
+///     enabled: bool,
+/// }
+///
+/// impl<T> Guarded<T> {
+///     fn inner(self: Pin<&mut Self>) -> Pin<&mut T> {
+///         // SAFETY: `inner` is structurally pinned.
+///         unsafe { self.map_unchecked_mut(|this| &mut this.inner) }
+///     }
+/// }
+///
+/// #[pin_data]
+/// struct DriverData {
+///     #[pin]
+///     queue: Guarded<Mutex<KVec<Command>>>,
+/// }
+///
+/// impl DriverData {
+///     fn queue(self: Pin<&mut Self>) -> Pin<&mut Mutex<KVec<Command>>> {
+///         // SAFETY: `queue` is structurally pinned.
+///         unsafe { self.map_unchecked_mut(|this| &mut this.queue) }.inner()
+///     }
+/// }
+/// ```
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::sync::Mutex;
+/// # use core::{mem::ManuallyDrop, pin::Pin};
+/// use kernel::macros::{pin_data, pinned_drop};
+///
+/// #[pin_data(PinnedDrop)]
+/// struct Teardown {
+///     #[pin]
+///     queue: ManuallyDrop<Mutex<u32>>,
+///     #[pin]
+///     hw: ManuallyDrop<Mutex<u32>>,
+/// }
+///
+/// impl Teardown {
+///     fn queue(self: Pin<&mut Self>) -> Pin<&mut ManuallyDrop<Mutex<u32>>> {
+///         // SAFETY: `queue` is structurally pinned.
+///         unsafe { self.map_unchecked_mut(|this| &mut this.queue) }
+///     }
+/// }
+///
+/// #[pinned_drop]
+/// impl PinnedDrop for Teardown {
+///     fn drop(self: Pin<&mut Self>) {
+///         // SAFETY: The fields are dropped in place and are not used afterwards.
+///         let this = unsafe { self.get_unchecked_mut() };
+///         // The queue has to be torn down before the hardware.
+///         // SAFETY: See above.
+///         unsafe { ManuallyDrop::drop(&mut this.queue) };
+///         // SAFETY: See above.
+///         unsafe { ManuallyDrop::drop(&mut this.hw) };
+///     }
+/// }
+/// ```
+///
+/// A struct with a field that is not `Send` is not `Send` either:
+///
+/// ```compile_fail
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use core::marker::PhantomData;
+/// # use std::sync::Mutex;
+/// # use kernel::macros::pin_data;
+/// #[pin_data]
+/// struct PerCpu {
+///     #[pin]
+///     lock: Mutex<u32>,
+///     // Raw pointers are neither `Send` nor `Sync`.
+///     cpu_local: PhantomData<*mut ()>,
+/// }
+///
+/// fn assert_send<T: Send>() {}
+///
+/// // error: `*mut ()` cannot be sent between threads safely
+/// assert_send::<PerCpu>();
+/// ```
+///
+/// Fields can be conditionally compiled with `#[cfg]`, its attributes including `#[pin]` are
+/// forwarded together with it, so the field and its projection exist in the same
+/// configurations. A field that always exists but is only pinned in some configurations is
+/// marked with `#[cfg_attr(pred, pin)]`. It is expanded to a `#[cfg(pred)] #[pin]` field and a
+/// `#[cfg(not(pred))]` field of the same name. `pin` has to be the only attribute of such a
+/// `cfg_attr`.
+///
+/// Here `all()` and `any()` stand for a configuration option that is enabled and disabled
+/// respectively:
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::sync::Mutex;
+/// # use core::pin::Pin;
+/// # use kernel::{init::PinInit, macros::pin_data, pin_init};
+/// #[pin_data]
+/// struct Stats {
+///     #[cfg_attr(all(), pin)]
+///     rx: Mutex<u64>,
+///     #[cfg_attr(any(), pin)]
+///     tx: Mutex<u64>,
+///     #[cfg(any())]
+///     #[pin]
+///     errors: Mutex<u64>,
+/// }
+///
+/// impl Stats {
+///     // `errors` does not exist, so it is not initialized.
+///     fn new() -> impl PinInit<Self> {
+///         pin_init!(Self {
+///             rx <- Mutex::new(0),
+///             tx <- Mutex::new(0),
+///         })
+///     }
+///
+///     fn reset(self: Pin<&mut Self>) {
+///         let this = self.project();
+///         // `rx` is pinned, `tx` is not.
+///         let _rx: Pin<&mut Mutex<u64>> = this.rx;
+///         *this.tx = Mutex::new(0);
+///     }
+/// }
+///
+/// fn counters(stats: &Stats) -> (u64, u64) {
+///     (*stats.rx.lock().unwrap(), *stats.tx.lock().unwrap())
+/// }
+/// ```
+///
+/// # Tuple structs
+///
+/// [`pin_init!`] initializes fields by their name, so `#[pin_data]` cannot be used on tuple
+/// structs. Use a struct with named fields instead:
+///
+/// ```compile_fail
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use std::sync::Mutex;
+/// # use kernel::macros::pin_data;
+/// // error: `#[pin_data]` cannot be used on tuple structs
+/// #[pin_data]
+/// pub struct Counter(#[pin] Mutex<u64>, &'static str);
+/// ```
+///
+/// [`Pin::as_mut`]: core::pin::Pin::as_mut
+/// [`pin_init!`]: ../kernel/macro.pin_init.html
+//  ^ cannot use direct link, since `kernel` is not a dependency of `macros`.
+#[proc_macro_attribute]
+pub fn pin_data(inner: TokenStream, item: TokenStream) -> TokenStream {
+    pin_data::pin_data(inner, item)
+}
+
+/// Used to implement `PinnedDrop` safely.
+///
+/// Only works on structs that are annotated via `#[`[`macro@pin_data`]`]`.
+///
+/// # Examples
+///
+/// ```
+/// # #![feature(lint_reasons)]
+/// # use kernel::prelude::*;
+/// # use macros::{pin_data, pinned_drop};
+/// # use std::{sync::Mutex, process::Command};
+/// # use core::pin::Pin;
+/// # mod bindings {
+/// #     pub struct Info;
+/// #     pub unsafe fn destroy_info(_ptr: *mut Info) {}
+/// # }
+/// #[pin_data(PinnedDrop)]
+/// struct DriverData {
+///     #[pin]
+///     queue: Mutex<KVec<Command>>,
+///     buf: KBox<[u8; 1024 * 1024]>,
+///     raw_info: *mut bindings::Info,
+/// }
+///
+/// #[pinned_drop]
+/// impl PinnedDrop for DriverData {
+///     fn drop(self: Pin<&mut Self>) {
+///         unsafe { bindings::destroy_info(self.raw_info) };
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn pinned_drop(args: TokenStream, input: TokenStream) -> TokenStream {
+    pinned_drop::pinned_drop(args, input)
+}
+
+/// Paste identifiers together.
+///
+/// Within the `paste!` macro, identifiers inside `[<` and `>]` are concatenated together to form a
+/// single identifier.
+///
+/// This is similar to the [`paste`] crate, but with pasting feature limited to identifiers and
+/// literals (lifetimes and documentation strings are not supported). There is a difference in
+/// supported modifiers as well.
+///
+/// # Example
+///
+/// ```
+/// # const binder_driver_return_protocol_BR_OK: u32 = 0;
+/// # const binder_driver_return_protocol_BR_ERROR: u32 = 1;
+/// # const binder_driver_return_protocol_BR_TRANSACTION: u32 = 2;
+/// # const binder_driver_return_protocol_BR_REPLY: u32 = 3;
+/// # const binder_driver_return_protocol_BR_DEAD_REPLY: u32 = 4;
+/// # const binder_driver_return_protocol_BR_TRANSACTION_COMPLETE: u32 = 5;
+/// # const binder_driver_return_protocol_BR_INCREFS: u32 = 6;
+/// # const binder_driver_return_protocol_BR_ACQUIRE: u32 = 7;
+/// # const binder_driver_return_protocol_BR_RELEASE: u32 = 8;
+/// # const binder_driver_return_protocol_BR_DECREFS: u32 = 9;
+/// # const binder_driver_return_protocol_BR_NOOP: u32 = 10;
+/// # const binder_driver_return_protocol_BR_SPAWN_LOOPER: u32 = 11;
+/// # const binder_driver_return_protocol_BR_DEAD_BINDER: u32 = 12;
+/// # const binder_driver_return_protocol_BR_CLEAR_DEATH_NOTIFICATION_DONE: u32 = 13;
+/// # const binder_driver_return_protocol_BR_FAILED_REPLY: u32 = 14;
+/// macro_rules! pub_no_prefix {
+///     ($prefix:ident, $($newname:ident),+) => {
+///         kernel::macros::paste! {
+///             $(pub(crate) const $newname: u32 = [<$prefix $newname>];)+
+///         }
+///     };
+/// }
+///
+/// pub_no_prefix!(
+///     binder_driver_return_protocol_,
+///     BR_OK,
+///     BR_ERROR,
+///     BR_TRANSACTION,
+///     BR_REPLY,
+///     BR_DEAD_REPLY,
+///     BR_TRANSACTION_COMPLETE,
+///     BR_INCREFS,
+///     BR_ACQUIRE,
+///     BR_RELEASE,
+///     BR_DECREFS,
+///     BR_NOOP,
+///     BR_SPAWN_LOOPER,
+///     BR_DEAD_BINDER,
+///     BR_CLEAR_DEATH_NOTIFICATION_DONE,
+///     BR_FAILED_REPLY
+/// );
+///
+/// assert_eq!(BR_OK, binder_driver_return_protocol_BR_OK);
+/// ```
+///
+/// Only identifiers, literals and modifiers may appear within `[<` and `>]`, other tokens are an
+/// error:
+///
+/// ```compile_fail
+/// kernel::macros::paste! {
+///     fn [<foo + bar>]() {}
+/// }
+/// ```
+///
+/// ```compile_fail
+/// kernel::macros::paste! {
+///     fn [<foo; bar>]() {}
+/// }
+/// ```
+///
+/// # Modifiers
+///
+/// For each identifier, it is possible to attach one or multiple modifiers to
+/// it.
+///
+/// Currently supported modifiers are:
+/// * `span`: change the span of concatenated identifier to the span of the specified token. By
+///   default the span of the `[< >]` group is used.
+/// * `span_only`: use the span of the specified token for the concatenated identifier instead of
+///   the span of the `[< >]` group. The token is not part of the identifier, it only serves as an
+///   anchor for the span.
+/// * `lower`: change the identifier to lower case.
+/// * `upper`: change the identifier to upper case.
+/// * `pad(width, 'c')`: left-pad an integer literal with the character `c` to `width` characters.
+///   Numbers that are already at least `width` characters long are kept as they are.
+/// * `ordinal`: replace an integer literal from 0 to 20 by its English ordinal word, e.g. `2`
+///   becomes `second`. The word can then be modified like an identifier.
+/// * `replace("from", "to")`: replace all occurrences of `from` by `to`.
+/// * `reverse`: reverse the characters of an identifier, string literal or integer literal.
+///   Characters are Unicode scalar values, so multi-byte characters are kept intact, but
+///   combining characters end up before the character they belong to.
+/// * `bstr`: emit the whole pasted text as a byte string literal instead of an identifier, see
+///   [Byte strings](#byte-strings). It must be the last token of the `[< >]` group.
+///
+/// Modifiers are applied from left to right, so `[<$name:replace("__", "_"):upper>]` replaces
+/// before changing the case, while `:upper:replace(...)` needs `from` to be upper case already.
+///
+/// `lower`, `upper` and `replace` can only be applied to identifiers and string literals, `pad`
+/// and `ordinal` only to integer literals, and `reverse` to any of them. Applying them to other
+/// segments is an error:
+///
+/// ```compile_fail
+/// kernel::macros::paste! {
+///     fn [<reg_ 0x1f:upper>]() {}
+/// }
+/// ```
+///
+/// ```
+/// # const binder_driver_return_protocol_BR_OK: u32 = 0;
+/// # const binder_driver_return_protocol_BR_ERROR: u32 = 1;
+/// # const binder_driver_return_protocol_BR_TRANSACTION: u32 = 2;
+/// # const binder_driver_return_protocol_BR_REPLY: u32 = 3;
+/// # const binder_driver_return_protocol_BR_DEAD_REPLY: u32 = 4;
+/// # const binder_driver_return_protocol_BR_TRANSACTION_COMPLETE: u32 = 5;
+/// # const binder_driver_return_protocol_BR_INCREFS: u32 = 6;
+/// # const binder_driver_return_protocol_BR_ACQUIRE: u32 = 7;
+/// # const binder_driver_return_protocol_BR_RELEASE: u32 = 8;
+/// # const binder_driver_return_protocol_BR_DECREFS: u32 = 9;
+/// # const binder_driver_return_protocol_BR_NOOP: u32 = 10;
+/// # const binder_driver_return_protocol_BR_SPAWN_LOOPER: u32 = 11;
+/// # const binder_driver_return_protocol_BR_DEAD_BINDER: u32 = 12;
+/// # const binder_driver_return_protocol_BR_CLEAR_DEATH_NOTIFICATION_DONE: u32 = 13;
+/// # const binder_driver_return_protocol_BR_FAILED_REPLY: u32 = 14;
+/// macro_rules! pub_no_prefix {
+///     ($prefix:ident, $($newname:ident),+) => {
+///         kernel::macros::paste! {
+///             $(pub(crate) const fn [<$newname:lower:span>]() -> u32 { [<$prefix $newname:span>] })+
+///         }
+///     };
+/// }
+///
+/// pub_no_prefix!(
+///     binder_driver_return_protocol_,
+///     BR_OK,
+///     BR_ERROR,
+///     BR_TRANSACTION,
+///     BR_REPLY,
+///     BR_DEAD_REPLY,
+///     BR_TRANSACTION_COMPLETE,
+///     BR_INCREFS,
+///     BR_ACQUIRE,
+///     BR_RELEASE,
+///     BR_DECREFS,
+///     BR_NOOP,
+///     BR_SPAWN_LOOPER,
+///     BR_DEAD_BINDER,
+///     BR_CLEAR_DEATH_NOTIFICATION_DONE,
+///     BR_FAILED_REPLY
+/// );
+///
+/// assert_eq!(br_ok(), binder_driver_return_protocol_BR_OK);
+/// ```
+///
+/// With `span_only`, a token of the caller can make an identifier chosen by the macro visible to
+/// the caller, e.g. a local variable, without changing its name:
+///
+/// ```
+/// macro_rules! define_value {
+///     ($anchor:ident) => {
+///         kernel::macros::paste! {
+///             let [<value $anchor:span_only>] = 42;
+///         }
+///     };
+/// }
+///
+/// define_value!(here);
+///
+/// assert_eq!(value, 42);
+/// ```
+///
+/// The `replace` modifier substitutes text in a segment, e.g. to collapse double underscores:
+///
+/// ```
+/// macro_rules! create_getter {
+///     ($name:ident) => {
+///         kernel::macros::paste! {
+///             fn [<get_ $name:replace("__", "_")>]() -> u32 { 1 }
+///         }
+///     };
+/// }
+///
+/// create_getter!(dev__id);
+/// create_getter!(dev__irq__count);
+///
+/// assert_eq!(get_dev_id(), 1);
+/// assert_eq!(get_dev_irq_count(), 1);
+/// ```
+///
+/// # Literals
+///
+/// Literals can also be concatenated with other identifiers:
+///
+/// ```
+/// macro_rules! create_numbered_fn {
+///     ($name:literal, $val:literal) => {
+///         kernel::macros::paste! {
+///             fn [<some_ $name _fn $val>]() -> u32 { $val }
+///         }
+///     };
+/// }
+///
+/// create_numbered_fn!("foo", 100);
+///
+/// assert_eq!(some_foo_fn100(), 100)
+/// ```
+///
+/// Integer literals can be zero-padded with the `pad` modifier:
+///
+/// ```
+/// macro_rules! create_reg_fn {
+///     ($($reg:literal),*) => {
+///         kernel::macros::paste! {
+///             $(fn [<reg_ $reg:pad(3, '0')>]() -> u32 { $reg })*
+///         }
+///     };
+/// }
+///
+/// create_reg_fn!(7, 42, 255, 1024);
+///
+/// assert_eq!(reg_007(), 7);
+/// assert_eq!(reg_042(), 42);
+/// assert_eq!(reg_255(), 255);
+/// assert_eq!(reg_1024(), 1024);
+/// ```
+///
+/// The `ordinal` modifier spells out small integer literals, e.g. for per-port accessors:
+///
+/// ```
+/// macro_rules! create_port_fn {
+///     ($($port:literal),*) => {
+///         kernel::macros::paste! {
+///             $(fn [<$port:ordinal _port>]() -> u32 { $port })*
+///             $(const [<$port:ordinal:upper _PORT>]: u32 = $port;)*
+///         }
+///     };
+/// }
+///
+/// create_port_fn!(0, 1, 2, 12, 20);
+///
+/// assert_eq!(zeroth_port(), 0);
+/// assert_eq!(first_port(), 1);
+/// assert_eq!(second_port(), 2);
+/// assert_eq!(twelfth_port(), 12);
+/// assert_eq!(twentieth_port(), 20);
+/// assert_eq!(SECOND_PORT, 2);
+/// ```
+///
+/// Numbers above 20 cannot be spelled out:
+///
+/// ```compile_fail
+/// kernel::macros::paste! {
+///     fn [<port_ 21:ordinal>]() {}
+/// }
+/// ```
+///
+/// The `reverse` modifier reverses the characters of a segment, also of non-ASCII identifiers and
+/// string literals:
+///
+/// ```
+/// macro_rules! create_reversed {
+///     ($name:ident, $tag:literal, $id:literal) => {
+///         kernel::macros::paste! {
+///             fn [<$name:reverse _ $tag:reverse _ $id:reverse>]() -> &'static str {
+///                 stringify!($name)
+///             }
+///         }
+///     };
+/// }
+///
+/// create_reversed!(abc, "xyz", 120);
+/// create_reversed!(größe, "ñandú", 7);
+///
+/// assert_eq!(cba_zyx_021(), "abc");
+/// assert_eq!(eßörg_údnañ_7(), "größe");
+/// ```
+///
+/// If the result starts with a digit, it is emitted as a numeric literal instead of an
+/// identifier, so it can be used in expressions:
+///
+/// ```
+/// macro_rules! create_const {
+///     ($name:ident, $hi:literal, $lo:literal) => {
+///         kernel::macros::paste! {
+///             const $name: u32 = [<$hi $lo>];
+///         }
+///     };
+/// }
+///
+/// create_const!(VERSION, 1, 2);
+///
+/// assert_eq!(VERSION, 12);
+/// ```
+///
+/// Boolean literals are pasted as `true` and `false`:
+///
+/// ```
+/// macro_rules! create_handler {
+///     ($enabled:literal) => {
+///         kernel::macros::paste! {
+///             fn [<handler_ $enabled>]() -> bool { $enabled }
+///         }
+///     };
+/// }
+///
+/// create_handler!(true);
+/// create_handler!(false);
+///
+/// assert!(handler_true());
+/// assert!(!handler_false());
+/// ```
+///
+/// # Byte strings
+///
+/// With a trailing `:bstr`, the concatenated text is emitted as a byte string literal, e.g. for
+/// tables of names passed to C. The text has to be ASCII:
+///
+/// ```
+/// macro_rules! reg_names {
+///     ($($reg:ident),*) => {
+///         kernel::macros::paste! {
+///             [$([<reg_ $reg:lower:bstr>]),*]
+///         }
+///     };
+/// }
+///
+/// let names: [&[u8]; 2] = reg_names!(CTRL, STATUS);
 ///
+/// assert_eq!(names, [&b"reg_ctrl"[..], b"reg_status"]);
 /// ```
-/// # const binder_driver_return_protocol_BR_OK: u32 = 0;
-/// # const binder_driver_return_protocol_BR_ERROR: u32 = 1;
-/// # const binder_driver_return_protocol_BR_TRANSACTION: u32 = 2;
-/// # const binder_driver_return_protocol_BR_REPLY: u32 = 3;
-/// # const binder_driver_return_protocol_BR_DEAD_REPLY: u32 = 4;
-/// # const binder_driver_return_protocol_BR_TRANSACTION_COMPLETE: u32 = 5;
-/// # const binder_driver_return_protocol_BR_INCREFS: u32 = 6;
-/// # const binder_driver_return_protocol_BR_ACQUIRE: u32 = 7;
-/// # const binder_driver_return_protocol_BR_RELEASE: u32 = 8;
-/// # const binder_driver_return_protocol_BR_DECREFS: u32 = 9;
-/// # const binder_driver_return_protocol_BR_NOOP: u32 = 10;
-/// # const binder_driver_return_protocol_BR_SPAWN_LOOPER: u32 = 11;
-/// # const binder_driver_return_protocol_BR_DEAD_BINDER: u32 = 12;
-/// # const binder_driver_return_protocol_BR_CLEAR_DEATH_NOTIFICATION_DONE: u32 = 13;
-/// # const binder_driver_return_protocol_BR_FAILED_REPLY: u32 = 14;
-/// use kernel::macros::concat_idents;
 ///
-/// macro_rules! pub_no_prefix {
-///     ($prefix:ident, $($newname:ident),+) => {
-///         $(pub(crate) const $newname: u32 = concat_idents!($prefix, $newname);)+
+/// ```compile_fail
+/// kernel::macros::paste! {
+///     // error: `bstr` modifier requires ASCII text
+///     const NAME: &[u8] = [<reg_ "été":bstr>];
+/// }
+/// ```
+///
+/// # Struct expressions
+///
+/// `[< >]` groups are replaced everywhere, so they can also name the fields in struct expressions
+/// and patterns, e.g. to initialize a register block:
+///
+/// ```
+/// struct Regs {
+///     reg_0: u32,
+///     reg_1: u32,
+/// }
+///
+/// macro_rules! regs {
+///     ($($n:literal => $value:expr),*) => {
+///         kernel::macros::paste! {
+///             Regs { $([<reg_ $n>]: $value),* }
+///         }
 ///     };
 /// }
 ///
-/// pub_no_prefix!(
-///     binder_driver_return_protocol_,
-///     BR_OK,
-///     BR_ERROR,
-///     BR_TRANSACTION,
-///     BR_REPLY,
-///     BR_DEAD_REPLY,
-///     BR_TRANSACTION_COMPLETE,
-///     BR_INCREFS,
-///     BR_ACQUIRE,
-///     BR_RELEASE,
-///     BR_DECREFS,
-///     BR_NOOP,
-///     BR_SPAWN_LOOPER,
-///     BR_DEAD_BINDER,
-///     BR_CLEAR_DEATH_NOTIFICATION_DONE,
-///     BR_FAILED_REPLY
-/// );
+/// let regs = regs!(0 => 0x10, 1 => 0x20);
 ///
-/// assert_eq!(BR_OK, binder_driver_return_protocol_BR_OK);
+/// assert_eq!(regs.reg_0, 0x10);
+/// assert_eq!(regs.reg_1, 0x20);
+/// ```
+///
+/// # Closures and match arms
+///
+/// `[< >]` groups are also replaced in closure parameters and in the paths of match arm patterns:
+///
+/// ```
+/// enum Reg {
+///     Ctrl0,
+///     Ctrl1,
+/// }
+///
+/// macro_rules! reg_index {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             |reg: Reg| match reg {
+///                 Reg::[<Ctrl $n>] => Some($n),
+///                 _ => None,
+///             }
+///         }
+///     };
+/// }
+///
+/// macro_rules! scale {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             |[<value_ $n>]: u32| [<value_ $n>] * $n
+///         }
+///     };
+/// }
+///
+/// assert_eq!(reg_index!(1)(Reg::Ctrl1), Some(1));
+/// assert_eq!(reg_index!(1)(Reg::Ctrl0), None);
+/// assert_eq!(scale!(3)(2), 6);
+/// ```
+///
+/// # Modules
+///
+/// Pasted identifiers can also name modules and be used in paths, including in `use`
+/// declarations:
+///
+/// ```
+/// macro_rules! create_submodule {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             mod [<submod_ $n>] {
+///                 pub(crate) const VALUE: u32 = $n;
+///             }
+///
+///             use [<submod_ $n>]::VALUE as [<VALUE_ $n>];
+///         }
+///     };
+/// }
+///
+/// create_submodule!(1);
+/// create_submodule!(2);
+///
+/// assert_eq!(VALUE_1, 1);
+/// assert_eq!(submod_2::VALUE, 2);
+/// ```
+///
+/// # Generics
+///
+/// Only the `[< >]` groups are replaced, all other tokens, including lifetimes and generics in
+/// `impl` headers, are kept as they are:
+///
+/// ```
+/// trait Get<'a> {
+///     fn get(&self) -> &'a u32;
+/// }
+///
+/// macro_rules! create_wrapper {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             struct [<Wrapper $n>]<'a>(&'a u32);
+///
+///             impl<'a> Get<'a> for [<Wrapper $n>]<'a> {
+///                 fn get(&self) -> &'a u32 {
+///                     self.0
+///                 }
+///             }
+///         }
+///     };
+/// }
+///
+/// create_wrapper!(1);
+///
+/// assert_eq!(*Wrapper1(&42).get(), 42);
+/// ```
+///
+/// This includes the bounded types in `where` clauses, e.g. for a family of generic impls:
+///
+/// ```
+/// trait Reg {
+///     const OFFSET: u32;
+/// }
+///
+/// struct Ctrl0;
+/// struct Ctrl1;
+///
+/// impl Reg for Ctrl0 {
+///     const OFFSET: u32 = 0x0;
+/// }
+///
+/// impl Reg for Ctrl1 {
+///     const OFFSET: u32 = 0x4;
+/// }
+///
+/// struct Bank<T>(T);
+///
+/// macro_rules! bank_offset {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             impl<T> Bank<T>
+///             where
+///                 [<Ctrl $n>]: Reg,
+///             {
+///                 fn [<ctrl $n _offset>](&self) -> u32 {
+///                     <[<Ctrl $n>] as Reg>::OFFSET
+///                 }
+///             }
+///         }
+///     };
+/// }
+///
+/// bank_offset!(0);
+/// bank_offset!(1);
+///
+/// assert_eq!(Bank(()).ctrl1_offset(), 0x4);
+/// ```
+///
+/// Generic argument lists are not delimited groups, so `[< >]` groups in associated type
+/// bindings, e.g. `Iterator<Item = [<Sample $n>]>`, are replaced like everywhere else:
+///
+/// ```
+/// macro_rules! create_sampler {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             #[derive(Debug, PartialEq)]
+///             struct [<Sample $n>](u32);
+///
+///             struct [<Sampler $n>](u32);
+///
+///             impl Iterator for [<Sampler $n>] {
+///                 type Item = [<Sample $n>];
+///
+///                 fn next(&mut self) -> Option<Self::Item> {
+///                     self.0 += $n;
+///                     Some([<Sample $n>](self.0))
+///                 }
+///             }
+///
+///             fn [<sampler $n>]() -> impl Iterator<Item = [<Sample $n>]> {
+///                 [<Sampler $n>](0)
+///             }
+///         }
+///     };
+/// }
+///
+/// create_sampler!(8);
+///
+/// assert_eq!(sampler8().nth(1), Some(Sample8(16)));
+/// ```
+///
+/// The same applies to const generic arguments. A pasted constant name is resolved like any other
+/// const argument, and a pasted number is emitted as a literal, so neither needs braces:
+///
+/// ```
+/// struct Ring<const N: usize>([u32; N]);
+///
+/// const RING_SMALL: usize = 4;
+///
+/// macro_rules! create_rings {
+///     ($size:ident, $k:literal) => {
+///         kernel::macros::paste! {
+///             type [<Ring $size:lower>] = Ring<[<RING_ $size>]>;
+///             type [<Ring $k 0>] = Ring<[<$k 0>]>;
+///         }
+///     };
+/// }
+///
+/// create_rings!(SMALL, 1);
+///
+/// assert_eq!(core::mem::size_of::<Ringsmall>(), 16);
+/// assert_eq!(core::mem::size_of::<Ring10>(), 40);
+/// ```
+///
+/// # Index
+///
+/// `#index` can be used as a segment to number the items generated by a repetition, without an
+/// external counter macro. Each `#index` is replaced by the number of `#index` segments before it
+/// in the same `paste!` invocation, counting from zero in the order in which they appear, including
+/// those in nested groups. Hence every `#index` gets a new number, so use it once per repetition:
+///
+/// ```
+/// macro_rules! create_handlers {
+///     ($($name:literal),*) => {
+///         kernel::macros::paste! {
+///             $(fn [<handler_ #index>]() -> &'static str { $name })*
+///         }
+///     };
+/// }
+///
+/// create_handlers!("reset", "irq", "dma");
+///
+/// assert_eq!(handler_0(), "reset");
+/// assert_eq!(handler_1(), "irq");
+/// assert_eq!(handler_2(), "dma");
+/// ```
+///
+/// If the index is needed more than once, it can be stored in an item, e.g. a constant:
+///
+/// ```
+/// macro_rules! create_regs {
+///     ($($name:ident),*) => {
+///         kernel::macros::paste! {
+///             $(const $name: u32 = [<#index>];)*
+///         }
+///     };
+/// }
+///
+/// create_regs!(CTRL, STATUS);
+///
+/// assert_eq!(CTRL, 0);
+/// assert_eq!(STATUS, 1);
+/// ```
+///
+/// # Escaping
+///
+/// To emit a `[< >]` group as-is, e.g. in the definition of a macro that uses `paste!` itself,
+/// write it as `[<< >>]`. The group is then neither concatenated nor searched for further groups:
+///
+/// ```
+/// kernel::macros::paste! {
+///     fn [<get_ status>]() -> u32 {
+///         1
+///     }
+///
+///     // The inner `paste!` only sees `$reg` when `get!` is used.
+///     macro_rules! get {
+///         ($reg:ident) => {
+///             kernel::macros::paste! {
+///                 [<<get_ $reg>>]()
+///             }
+///         };
+///     }
+/// }
+///
+/// assert_eq!(get!(status), 1);
+/// ```
+///
+/// # `$crate`
+///
+/// Tokens outside of `[< >]` groups are kept as they are, so `$crate` of an exported generating
+/// macro can precede a pasted name to refer to items of the crate defining the macro:
+///
+/// ```
+/// pub mod regs {
+///     pub const REG_0: u32 = 0x10;
+///
+///     pub fn read_1() -> u32 {
+///         0x20
+///     }
+/// }
+///
+/// #[macro_export]
+/// macro_rules! reg {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             $crate::regs::[<REG_ $n>]
+///         }
+///     };
+/// }
+///
+/// #[macro_export]
+/// macro_rules! read {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             $crate::regs::[<read_ $n>]()
+///         }
+///     };
+/// }
+///
+/// # fn main() {
+/// assert_eq!(reg!(0), 0x10);
+/// assert_eq!(read!(1), 0x20);
+/// # }
 /// ```
+///
+/// `$crate` cannot be a segment of a pasted name itself:
+///
+/// ```compile_fail
+/// macro_rules! reg {
+///     ($n:literal) => {
+///         kernel::macros::paste! {
+///             [<$crate REG_ $n>]
+///         }
+///     };
+/// }
+///
+/// let _ = reg!(0);
+/// ```
+///
+/// # Strict mode
+///
+/// A `paste!` invocation without any `[< >]` groups does nothing and is likely a mistake. Since
+/// macros may generate `paste!` invocations that only sometimes contain `[< >]` groups, this is
+/// only an error if the invocation starts with `#![strict]`:
+///
+/// ```compile_fail
+/// kernel::macros::paste! {
+///     #![strict]
+///     fn get_status() -> u32 { 0 }
+/// }
+/// ```
+///
+/// ```
+/// kernel::macros::paste! {
+///     #![strict]
+///     fn [<get_ status>]() -> u32 { 0 }
+/// }
+///
+/// assert_eq!(get_status(), 0);
+/// ```
+///
+/// # Maximum length
+///
+/// Pathological inputs can generate very long identifiers, which may cause obscure issues later,
+/// e.g. when symbol names are truncated. With `#![max_len = N]`, it is an error if a pasted
+/// identifier is longer than `N` characters, and the error points at the `[< >]` group. The
+/// length is counted in characters, not bytes, so non-ASCII identifiers are not penalized. With
+/// just `#![max_len]`, the limit is 255 characters. The markers can be combined with `#![strict]`:
+///
+/// ```compile_fail
+/// kernel::macros::paste! {
+///     #![strict]
+///     #![max_len = 16]
+///     // error: pasted identifier is 19 characters long, which exceeds the maximum of 16
+///     fn [<get_ status_ register>]() -> u32 { 0 }
+/// }
+/// ```
+///
+/// ```
+/// # #![allow(non_ascii_idents)]
+/// kernel::macros::paste! {
+///     #![max_len = 16]
+///     fn [<get_ status>]() -> u32 { 0 }
+///     // 16 characters, but 19 bytes.
+///     fn [<get_ größe_ zähler>]() -> u32 { 1 }
+/// }
+///
+/// assert_eq!(get_status(), 0);
+/// assert_eq!(get_größe_zähler(), 1);
+/// ```
+///
+/// [`paste`]: https://docs.rs/paste/
 #[proc_macro]
-pub fn concat_idents(ts: TokenStream) -> TokenStream {
-    concat_idents::concat_idents(ts)
+pub fn paste(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter().collect();
+    let options = match paste::parse_options(&mut tokens) {
+        Ok(options) => options,
+        Err(err) => return err.into_compile_error(),
+    };
+    match paste::expand(&mut tokens, &mut 0, options.max_len) {
+        Ok(()) => tokens.into_iter().collect(),
+        Err(err) => err.into_compile_error(),
+    }
 }
 
-/// Used to specify the pinning information of the fields of a struct.
+/// Derives the [`Zeroable`] trait for the given struct, union or enum.
 ///
-/// This is somewhat similar in purpose as
-/// [pin-project-lite](https://crates.io/crates/pin-project-lite).
-/// Place this macro on a struct definition and then `#[pin]` in front of the attributes of each
-/// field you want to structurally pin.
+/// This can only be used for structs and unions where every field implements the [`Zeroable`]
+/// trait, see [Unions](#unions), and for fieldless enums, see [Enums](#enums).
 ///
-/// This macro enables the use of the [`pin_init!`] macro. When pin-initializing a `struct`,
-/// then `#[pin]` directs the type of initializer that is required.
+/// # Examples
 ///
-/// If your `struct` implements `Drop`, then you need to add `PinnedDrop` as arguments to this
-/// macro, and change your `Drop` implementation to `PinnedDrop` annotated with
-/// `#[`[`macro@pinned_drop`]`]`, since dropping pinned values requires extra care.
+/// ```
+/// use kernel::macros::Zeroable;
+///
+/// #[derive(Zeroable)]
+/// pub struct DriverData {
+///     id: i64,
+///     buf_ptr: *mut u8,
+///     len: usize,
+/// }
+/// ```
+///
+/// The fields of tuple structs are checked in the same way:
+///
+/// ```
+/// use kernel::macros::Zeroable;
+///
+/// #[derive(Zeroable)]
+/// pub struct Counters(pub u64, pub(crate) [u32; 4]);
+/// ```
 ///
-/// # Examples
+/// Generic parameters are required to implement [`Zeroable`] in the generated implementation, in
+/// addition to any bounds that are already present, including those in a `where` clause:
 ///
 /// ```
-/// # #![feature(lint_reasons)]
-/// # use kernel::prelude::*;
-/// # use std::{sync::Mutex, process::Command};
-/// # use kernel::macros::pin_data;
-/// #[pin_data]
-/// struct DriverData {
-///     #[pin]
-///     queue: Mutex<KVec<Command>>,
-///     buf: KBox<[u8; 1024 * 1024]>,
+/// use kernel::macros::Zeroable;
+///
+/// #[derive(Zeroable)]
+/// pub struct Pair<T: Copy, U>
+/// where
+///     U: Clone,
+/// {
+///     first: T,
+///     second: U,
 /// }
 /// ```
 ///
+/// Arrays are zeroable if their elements are, so fields can also be nested arrays or arrays of
+/// other zeroable types. Const generic parameters, e.g. for the length of an array, are not
+/// required to implement [`Zeroable`]:
+///
 /// ```
-/// # #![feature(lint_reasons)]
-/// # use kernel::prelude::*;
-/// # use std::{sync::Mutex, process::Command};
-/// # use core::pin::Pin;
-/// # pub struct Info;
-/// # mod bindings {
-/// #     pub unsafe fn destroy_info(_ptr: *mut super::Info) {}
-/// # }
-/// use kernel::macros::{pin_data, pinned_drop};
+/// use kernel::macros::Zeroable;
 ///
-/// #[pin_data(PinnedDrop)]
-/// struct DriverData {
-///     #[pin]
-///     queue: Mutex<KVec<Command>>,
-///     buf: KBox<[u8; 1024 * 1024]>,
-///     raw_info: *mut Info,
+/// #[derive(Zeroable)]
+/// pub struct Descriptor {
+///     addr: u64,
+///     len: u32,
 /// }
 ///
-/// #[pinned_drop]
-/// impl PinnedDrop for DriverData {
-///     fn drop(self: Pin<&mut Self>) {
-///         unsafe { bindings::destroy_info(self.raw_info) };
-///     }
+/// #[derive(Zeroable)]
+/// pub struct DmaBuffer<const N: usize> {
+///     data: [[u8; 16]; N],
+///     descriptors: [Descriptor; 4],
 /// }
-/// # fn main() {}
 /// ```
 ///
-/// [`pin_init!`]: ../kernel/macro.pin_init.html
-//  ^ cannot use direct link, since `kernel` is not a dependency of `macros`.
-#[proc_macro_attribute]
-pub fn pin_data(inner: TokenStream, item: TokenStream) -> TokenStream {
-    pin_data::pin_data(inner, item)
-}
-
-/// Used to implement `PinnedDrop` safely.
+/// Every field is checked separately, so if a field does not implement [`Zeroable`], the error
+/// points at the type of that field:
 ///
-/// Only works on structs that are annotated via `#[`[`macro@pin_data`]`]`.
+/// ```compile_fail
+/// use kernel::macros::Zeroable;
 ///
-/// # Examples
+/// #[derive(Zeroable)]
+/// pub struct DriverData {
+///     id: i64,
+///     // error: the trait bound `&'static u8: Zeroable` is not satisfied
+///     name: &'static u8,
+/// }
+/// ```
+///
+/// # Raw pointers
+///
+/// Raw pointers are zeroable, the all-zeroes bit pattern is the null pointer. Where a null pointer
+/// would be a bug, `#[zeroable(deny_ptr)]` rejects fields that are raw pointers or arrays of them,
+/// so that e.g. `Option<NonNull<T>>` is used instead, which makes the null case explicit. Raw
+/// pointers behind type aliases or in other types are not detected:
 ///
 /// ```
-/// # #![feature(lint_reasons)]
-/// # use kernel::prelude::*;
-/// # use macros::{pin_data, pinned_drop};
-/// # use std::{sync::Mutex, process::Command};
-/// # use core::pin::Pin;
-/// # mod bindings {
-/// #     pub struct Info;
-/// #     pub unsafe fn destroy_info(_ptr: *mut Info) {}
-/// # }
-/// #[pin_data(PinnedDrop)]
-/// struct DriverData {
-///     #[pin]
-///     queue: Mutex<KVec<Command>>,
-///     buf: KBox<[u8; 1024 * 1024]>,
-///     raw_info: *mut bindings::Info,
-/// }
+/// use core::ptr::NonNull;
+/// use kernel::macros::Zeroable;
 ///
-/// #[pinned_drop]
-/// impl PinnedDrop for DriverData {
-///     fn drop(self: Pin<&mut Self>) {
-///         unsafe { bindings::destroy_info(self.raw_info) };
-///     }
+/// #[derive(Zeroable)]
+/// #[zeroable(deny_ptr)]
+/// pub struct DriverData {
+///     id: i64,
+///     buf: Option<NonNull<u8>>,
+///     len: usize,
 /// }
 /// ```
-#[proc_macro_attribute]
-pub fn pinned_drop(args: TokenStream, input: TokenStream) -> TokenStream {
-    pinned_drop::pinned_drop(args, input)
-}
-
-/// Paste identifiers together.
 ///
-/// Within the `paste!` macro, identifiers inside `[<` and `>]` are concatenated together to form a
-/// single identifier.
+/// ```compile_fail
+/// use kernel::macros::Zeroable;
 ///
-/// This is similar to the [`paste`] crate, but with pasting feature limited to identifiers and
-/// literals (lifetimes and documentation strings are not supported). There is a difference in
-/// supported modifiers as well.
+/// #[derive(Zeroable)]
+/// #[zeroable(deny_ptr)]
+/// pub struct DriverData {
+///     id: i64,
+///     // error: Raw pointer fields are not allowed with `#[zeroable(deny_ptr)]`
+///     buf_ptr: *mut u8,
+///     len: usize,
+/// }
+/// ```
 ///
-/// # Example
+/// ```compile_fail
+/// use kernel::macros::Zeroable;
 ///
+/// #[derive(Zeroable)]
+/// #[zeroable(deny_ptr)]
+/// // error: Raw pointer fields are not allowed with `#[zeroable(deny_ptr)]`
+/// pub struct Buffer(*mut u8, usize);
 /// ```
-/// # const binder_driver_return_protocol_BR_OK: u32 = 0;
-/// # const binder_driver_return_protocol_BR_ERROR: u32 = 1;
-/// # const binder_driver_return_protocol_BR_TRANSACTION: u32 = 2;
-/// # const binder_driver_return_protocol_BR_REPLY: u32 = 3;
-/// # const binder_driver_return_protocol_BR_DEAD_REPLY: u32 = 4;
-/// # const binder_driver_return_protocol_BR_TRANSACTION_COMPLETE: u32 = 5;
-/// # const binder_driver_return_protocol_BR_INCREFS: u32 = 6;
-/// # const binder_driver_return_protocol_BR_ACQUIRE: u32 = 7;
-/// # const binder_driver_return_protocol_BR_RELEASE: u32 = 8;
-/// # const binder_driver_return_protocol_BR_DECREFS: u32 = 9;
-/// # const binder_driver_return_protocol_BR_NOOP: u32 = 10;
-/// # const binder_driver_return_protocol_BR_SPAWN_LOOPER: u32 = 11;
-/// # const binder_driver_return_protocol_BR_DEAD_BINDER: u32 = 12;
-/// # const binder_driver_return_protocol_BR_CLEAR_DEATH_NOTIFICATION_DONE: u32 = 13;
-/// # const binder_driver_return_protocol_BR_FAILED_REPLY: u32 = 14;
-/// macro_rules! pub_no_prefix {
-///     ($prefix:ident, $($newname:ident),+) => {
-///         kernel::macros::paste! {
-///             $(pub(crate) const $newname: u32 = [<$prefix $newname>];)+
-///         }
-///     };
-/// }
 ///
-/// pub_no_prefix!(
-///     binder_driver_return_protocol_,
-///     BR_OK,
-///     BR_ERROR,
-///     BR_TRANSACTION,
-///     BR_REPLY,
-///     BR_DEAD_REPLY,
-///     BR_TRANSACTION_COMPLETE,
-///     BR_INCREFS,
-///     BR_ACQUIRE,
-///     BR_RELEASE,
-///     BR_DECREFS,
-///     BR_NOOP,
-///     BR_SPAWN_LOOPER,
-///     BR_DEAD_BINDER,
-///     BR_CLEAR_DEATH_NOTIFICATION_DONE,
-///     BR_FAILED_REPLY
-/// );
+/// # Drop
 ///
-/// assert_eq!(BR_OK, binder_driver_return_protocol_BR_OK);
-/// ```
+/// Zeroing a value does not run its destructor, and a type with a [`Drop`] impl may own something
+/// that a zeroed value does not. `#[zeroable(deny_drop)]` rejects types that implement [`Drop`]:
 ///
-/// # Modifiers
+/// ```compile_fail
+/// use kernel::macros::Zeroable;
 ///
-/// For each identifier, it is possible to attach one or multiple modifiers to
-/// it.
+/// // error: `Guard` implements `Drop`, which is not allowed with `#[zeroable(deny_drop)]`.
+/// #[derive(Zeroable)]
+/// #[zeroable(deny_drop)]
+/// pub struct Guard {
+///     count: u32,
+/// }
 ///
-/// Currently supported modifiers are:
-/// * `span`: change the span of concatenated identifier to the span of the specified token. By
-///   default the span of the `[< >]` group is used.
-/// * `lower`: change the identifier to lower case.
-/// * `upper`: change the identifier to upper case.
+/// impl Drop for Guard {
+///     fn drop(&mut self) {}
+/// }
+/// ```
+///
+/// Without it, a type with a [`Drop`] impl can derive [`Zeroable`] as before:
 ///
 /// ```
-/// # const binder_driver_return_protocol_BR_OK: u32 = 0;
-/// # const binder_driver_return_protocol_BR_ERROR: u32 = 1;
-/// # const binder_driver_return_protocol_BR_TRANSACTION: u32 = 2;
-/// # const binder_driver_return_protocol_BR_REPLY: u32 = 3;
-/// # const binder_driver_return_protocol_BR_DEAD_REPLY: u32 = 4;
-/// # const binder_driver_return_protocol_BR_TRANSACTION_COMPLETE: u32 = 5;
-/// # const binder_driver_return_protocol_BR_INCREFS: u32 = 6;
-/// # const binder_driver_return_protocol_BR_ACQUIRE: u32 = 7;
-/// # const binder_driver_return_protocol_BR_RELEASE: u32 = 8;
-/// # const binder_driver_return_protocol_BR_DECREFS: u32 = 9;
-/// # const binder_driver_return_protocol_BR_NOOP: u32 = 10;
-/// # const binder_driver_return_protocol_BR_SPAWN_LOOPER: u32 = 11;
-/// # const binder_driver_return_protocol_BR_DEAD_BINDER: u32 = 12;
-/// # const binder_driver_return_protocol_BR_CLEAR_DEATH_NOTIFICATION_DONE: u32 = 13;
-/// # const binder_driver_return_protocol_BR_FAILED_REPLY: u32 = 14;
-/// macro_rules! pub_no_prefix {
-///     ($prefix:ident, $($newname:ident),+) => {
-///         kernel::macros::paste! {
-///             $(pub(crate) const fn [<$newname:lower:span>]() -> u32 { [<$prefix $newname:span>] })+
+/// use kernel::macros::Zeroable;
+///
+/// #[derive(Zeroable)]
+/// pub struct Guard {
+///     count: u32,
+/// }
+///
+/// impl Drop for Guard {
+///     fn drop(&mut self) {
+///         if self.count != 0 {
+///             // Release the references.
 ///         }
-///     };
+///     }
 /// }
+/// ```
 ///
-/// pub_no_prefix!(
-///     binder_driver_return_protocol_,
-///     BR_OK,
-///     BR_ERROR,
-///     BR_TRANSACTION,
-///     BR_REPLY,
-///     BR_DEAD_REPLY,
-///     BR_TRANSACTION_COMPLETE,
-///     BR_INCREFS,
-///     BR_ACQUIRE,
-///     BR_RELEASE,
-///     BR_DECREFS,
-///     BR_NOOP,
-///     BR_SPAWN_LOOPER,
-///     BR_DEAD_BINDER,
-///     BR_CLEAR_DEATH_NOTIFICATION_DONE,
-///     BR_FAILED_REPLY
-/// );
+/// Whether a type implements [`Drop`] can only be decided for concrete types, so generic types
+/// are skipped: `#[zeroable(deny_drop)]` on a generic type is accepted without checking anything.
+///
+/// # Unions
+///
+/// The all-zeroes bit pattern of a union has to be valid for every field, since any of them can be
+/// read after the union has been zeroed. Thus every field has to implement [`Zeroable`], e.g. for
+/// a scratch buffer shared with C:
 ///
-/// assert_eq!(br_ok(), binder_driver_return_protocol_BR_OK);
 /// ```
+/// use kernel::macros::Zeroable;
 ///
-/// # Literals
+/// #[derive(Zeroable, Clone, Copy)]
+/// #[repr(C)]
+/// pub union Scratch {
+///     words: [u32; 4],
+///     bytes: [u8; 16],
+/// }
+/// ```
 ///
-/// Literals can also be concatenated with other identifiers:
+/// ```compile_fail
+/// use kernel::macros::Zeroable;
 ///
+/// #[derive(Zeroable, Clone, Copy)]
+/// #[repr(C)]
+/// pub union Scratch {
+///     words: [u32; 4],
+///     // error: the trait bound `&'static u8: Zeroable` is not satisfied
+///     first: &'static u8,
+/// }
 /// ```
-/// macro_rules! create_numbered_fn {
-///     ($name:literal, $val:literal) => {
-///         kernel::macros::paste! {
-///             fn [<some_ $name _fn $val>]() -> u32 { $val }
-///         }
-///     };
+///
+/// Generic unions are supported as well. The fields are checked with the generics and the
+/// `where` clause of the union, and the type parameters are bounded by [`Zeroable`]. An error
+/// points at the type of the field that does not implement it:
+///
+/// ```
+/// use kernel::macros::Zeroable;
+///
+/// #[derive(Zeroable, Clone, Copy)]
+/// #[repr(C)]
+/// pub union Slot<T, const N: usize>
+/// where
+///     T: Copy,
+/// {
+///     value: T,
+///     values: [T; N],
+///     raw: [u8; N],
+///     len: usize,
 /// }
+/// ```
 ///
-/// create_numbered_fn!("foo", 100);
+/// ```compile_fail
+/// use kernel::macros::Zeroable;
 ///
-/// assert_eq!(some_foo_fn100(), 100)
+/// #[derive(Zeroable, Clone, Copy)]
+/// #[repr(C)]
+/// pub union Slot<T: Copy> {
+///     value: T,
+///     // error: the trait bound `fn(T): Zeroable` is not satisfied
+///     callback: fn(T),
+/// }
 /// ```
 ///
-/// [`paste`]: https://docs.rs/paste/
-#[proc_macro]
-pub fn paste(input: TokenStream) -> TokenStream {
-    let mut tokens = input.into_iter().collect();
-    paste::expand(&mut tokens);
-    tokens.into_iter().collect()
-}
-
-/// Derives the [`Zeroable`] trait for the given struct.
+/// # Enums
 ///
-/// This can only be used for structs where every field implements the [`Zeroable`] trait.
+/// A fieldless enum is zeroable if one of its variants has the discriminant zero. The type of
+/// the discriminant has to be fixed with an integer `#[repr(...)]`, since the layout the compiler
+/// chooses otherwise is not guaranteed:
 ///
-/// # Examples
+/// ```
+/// use kernel::macros::Zeroable;
 ///
+/// #[derive(Zeroable)]
+/// #[repr(u8)]
+/// pub enum State {
+///     Running = 1,
+///     Stopped = 0,
+/// }
 /// ```
+///
+/// ```compile_fail
 /// use kernel::macros::Zeroable;
 ///
+/// // error: `Zeroable` can only be derived for enums with an integer `#[repr(...)]`
 /// #[derive(Zeroable)]
-/// pub struct DriverData {
-///     id: i64,
-///     buf_ptr: *mut u8,
-///     len: usize,
+/// pub enum State {
+///     Stopped,
+///     Running,
 /// }
 /// ```
-#[proc_macro_derive(Zeroable)]
+#[proc_macro_derive(Zeroable, attributes(zeroable))]
 pub fn derive_zeroable(input: TokenStream) -> TokenStream {
     zeroable::derive(input)
 }
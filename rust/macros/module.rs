@@ -1,18 +1,29 @@
 // SPDX-License-Identifier: GPL-2.0
 
 use crate::helpers::*;
-use proc_macro::{token_stream, Delimiter, Literal, TokenStream, TokenTree};
+use proc_macro::{token_stream, Delimiter, Literal, Span, TokenStream, TokenTree};
 use std::fmt::Write;
 
 fn expect_string_array(it: &mut token_stream::IntoIter) -> Vec<String> {
+    expect_spanned_string_array(it)
+        .into_iter()
+        .map(|(val, _)| val)
+        .collect()
+}
+
+/// Like [`expect_string_array`], but also returns the span of each string for errors.
+fn expect_spanned_string_array(it: &mut token_stream::IntoIter) -> Vec<(String, Span)> {
     let group = expect_group(it);
     assert_eq!(group.delimiter(), Delimiter::Bracket);
     let mut values = Vec::new();
     let mut it = group.stream().into_iter();
 
-    while let Some(val) = try_string(&mut it) {
+    while let Some(span) = it.clone().next().map(|tt| tt.span()) {
+        let Some(val) = try_string(&mut it) else {
+            break;
+        };
         assert!(val.is_ascii(), "Expected ASCII string");
-        values.push(val);
+        values.push((val, span));
         match it.next() {
             Some(TokenTree::Punct(punct)) => assert_eq!(punct.as_char(), ','),
             None => break,
@@ -22,6 +33,121 @@ fn expect_string_array(it: &mut token_stream::IntoIter) -> Vec<String> {
     values
 }
 
+/// The modinfo tags that modpost writes into the `.mod.c` file of a module itself.
+///
+/// Setting them by hand would add a second, conflicting entry, e.g. `intree` would hide that an
+/// out-of-tree module taints the kernel.
+const MODPOST_TAGS: &[&str] = &[
+    "intree",
+    "retpoline",
+    "staging",
+    "depends",
+    "vermagic",
+    "name",
+];
+
+/// The modinfo tags that `module!` generates from its keys.
+///
+/// Setting them by hand would bypass the checks of the keys, e.g. `livepatch`, or add a second
+/// entry, e.g. a `license` that hides that a module with another license taints the kernel.
+const MODULE_TAGS: &[&str] = &[
+    "author",
+    "description",
+    "license",
+    "license_file",
+    "license_note",
+    "alias",
+    "firmware",
+    "livepatch",
+    "scmversion",
+    "abi_version",
+    "compression",
+    "type",
+    "file",
+];
+
+fn expect_info_map(it: &mut token_stream::IntoIter) -> Vec<(String, String)> {
+    let group = expect_group(it);
+    assert_eq!(group.delimiter(), Delimiter::Brace);
+    let mut entries: Vec<(String, String)> = Vec::new();
+    let mut it = group.stream().into_iter();
+
+    while let Some(tag) = try_ident(&mut it) {
+        assert_eq!(expect_punct(&mut it), ':');
+        // Boolean flags are normalized the same way the C side spells them.
+        let value = match it.clone().next() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "true" => {
+                it.next();
+                "Y".to_string()
+            }
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "false" => {
+                it.next();
+                "N".to_string()
+            }
+            _ => expect_string_ascii(&mut it),
+        };
+        if entries.iter().any(|(e, _)| *e == tag) {
+            panic!("Duplicated info tag \"{}\".", tag);
+        }
+        if MODPOST_TAGS.contains(&tag.as_str()) {
+            panic!(
+                "The info tag \"{}\" is generated by modpost and cannot be set by the module.",
+                tag
+            );
+        }
+        if MODULE_TAGS.contains(&tag.as_str()) {
+            panic!(
+                "The info tag \"{}\" is generated by `module!` from its keys and cannot be set \
+                    with `info`.",
+                tag
+            );
+        }
+        entries.push((tag, value));
+        match it.next() {
+            Some(TokenTree::Punct(punct)) => assert_eq!(punct.as_char(), ','),
+            None => break,
+            _ => panic!("Expected ',' or end of info"),
+        }
+    }
+    expect_end(&mut it);
+    entries
+}
+
+fn expect_c_identifier(it: &mut token_stream::IntoIter) -> String {
+    let symbol = expect_string_ascii(it);
+    if !symbol.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        || !symbol
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    {
+        panic!("Invalid C identifier \"{}\".", symbol);
+    }
+    symbol
+}
+
+/// Returns the section of the given initcall level, named like the C `*_initcall` macros.
+fn initcall_section(level: &str) -> Option<&'static str> {
+    Some(match level {
+        "pure" => ".initcall0.init",
+        "core" => ".initcall1.init",
+        "core_sync" => ".initcall1s.init",
+        "postcore" => ".initcall2.init",
+        "postcore_sync" => ".initcall2s.init",
+        "arch" => ".initcall3.init",
+        "arch_sync" => ".initcall3s.init",
+        "subsys" => ".initcall4.init",
+        "subsys_sync" => ".initcall4s.init",
+        "fs" => ".initcall5.init",
+        "fs_sync" => ".initcall5s.init",
+        "rootfs" => ".initcallrootfs.init",
+        "device" => ".initcall6.init",
+        "device_sync" => ".initcall6s.init",
+        "late" => ".initcall7.init",
+        "late_sync" => ".initcall7s.init",
+        _ => return None,
+    })
+}
+
 struct ModInfoBuilder<'a> {
     module: &'a str,
     counter: usize,
@@ -89,16 +215,83 @@ fn emit(&mut self, field: &str, content: &str) {
     }
 }
 
+/// Parses a type, e.g. `MyModule<Config>`, up to the next `,` that is not nested in `<>`.
+fn expect_type(it: &mut token_stream::IntoIter) -> TokenStream {
+    let mut ty = Vec::new();
+    // The current level of `<`.
+    let mut nesting = 0;
+    loop {
+        let mut lookahead = it.clone();
+        match lookahead.next() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ',' && nesting == 0 => break,
+            Some(token) => {
+                match &token {
+                    TokenTree::Punct(punct) if punct.as_char() == '<' => nesting += 1,
+                    TokenTree::Punct(punct) if punct.as_char() == '>' => nesting -= 1,
+                    _ => (),
+                }
+                ty.push(token);
+                *it = lookahead;
+            }
+            None => break,
+        }
+    }
+    if ty.is_empty() {
+        panic!("Expected a type");
+    }
+    ty.into_iter().collect()
+}
+
+/// Whether `compatible` is a device tree compatible of the form `vendor,device`.
+///
+/// The vendor prefix consists of alphanumeric characters and `-`, the device name may also contain
+/// `,`, `.`, `_` and `+`.
+fn is_of_compatible(compatible: &str) -> bool {
+    let Some((vendor, device)) = compatible.split_once(',') else {
+        return false;
+    };
+    !vendor.is_empty()
+        && !device.is_empty()
+        && vendor
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        && device
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b",._+-".contains(&b))
+}
+
+/// Compression formats supported by `CONFIG_MODULE_COMPRESS_*`, or `none`.
+const COMPRESSIONS: &[&str] = &["none", "gzip", "xz", "zstd"];
+
 #[derive(Debug, Default)]
 struct ModuleInfo {
     type_: String,
+    type_tokens: TokenStream,
     license: String,
     name: String,
     author: Option<String>,
     authors: Option<Vec<String>>,
     description: Option<String>,
+    license_files: Option<Vec<String>>,
+    license_note: Option<String>,
     alias: Option<Vec<String>>,
+    of_match: Option<Vec<String>>,
     firmware: Option<Vec<String>>,
+    firmware_modular_only: bool,
+    strict: bool,
+    livepatch: bool,
+    scmversion: Option<String>,
+    abi_version: Option<String>,
+    compression: Option<String>,
+    info: Option<Vec<(String, String)>>,
+    module_type: Option<String>,
+    initcall_level: Option<String>,
+    init_symbol: Option<String>,
+    exit_symbol: Option<String>,
+    data_section: Option<String>,
+    test_stub: bool,
+    /// Errors that point at the input, emitted together with the generated code.
+    errors: TokenStream,
 }
 
 impl ModuleInfo {
@@ -112,8 +305,24 @@ fn parse(it: &mut token_stream::IntoIter) -> Self {
             "authors",
             "description",
             "license",
+            "license_files",
+            "license_note",
             "alias",
+            "of_match",
             "firmware",
+            "firmware_modular_only",
+            "strict",
+            "livepatch",
+            "scmversion",
+            "abi_version",
+            "compression",
+            "info",
+            "module_type",
+            "initcall_level",
+            "init_symbol",
+            "exit_symbol",
+            "data_section",
+            "test_stub",
         ];
         const REQUIRED_KEYS: &[&str] = &["type", "name", "license"];
         let mut seen_keys = Vec::new();
@@ -135,14 +344,126 @@ fn parse(it: &mut token_stream::IntoIter) -> Self {
             assert_eq!(expect_punct(it), ':');
 
             match key.as_str() {
-                "type" => info.type_ = expect_ident(it),
+                "type" => {
+                    info.type_tokens = expect_type(it);
+                    info.type_ = info.type_tokens.to_string();
+                }
                 "name" => info.name = expect_string_ascii(it),
                 "author" => info.author = Some(expect_string(it)),
                 "authors" => info.authors = Some(expect_string_array(it)),
                 "description" => info.description = Some(expect_string(it)),
                 "license" => info.license = expect_string_ascii(it),
+                "license_files" => {
+                    let files = expect_string_array(it);
+                    for file in &files {
+                        if file.is_empty()
+                            || file.starts_with('/')
+                            || file
+                                .split('/')
+                                .any(|c| c.is_empty() || c == "." || c == "..")
+                            || file.bytes().any(|b| b.is_ascii_whitespace())
+                        {
+                            panic!("Invalid license file path \"{}\".", file);
+                        }
+                    }
+                    info.license_files = Some(files);
+                }
+                "license_note" => info.license_note = Some(expect_string_ascii(it)),
                 "alias" => info.alias = Some(expect_string_array(it)),
-                "firmware" => info.firmware = Some(expect_string_array(it)),
+                "of_match" => {
+                    let compatibles = expect_string_array(it);
+                    for (i, compatible) in compatibles.iter().enumerate() {
+                        if !is_of_compatible(compatible) {
+                            panic!(
+                                "Invalid compatible \"{}\", expected \"vendor,device\".",
+                                compatible
+                            );
+                        }
+                        if compatibles[..i].contains(compatible) {
+                            panic!("Duplicated compatible \"{}\".", compatible);
+                        }
+                    }
+                    info.of_match = Some(compatibles);
+                }
+                "firmware" => {
+                    let firmware = expect_spanned_string_array(it);
+                    for (i, (fw, span)) in firmware.iter().enumerate() {
+                        if let Some(first) = firmware[..i].iter().position(|(f, _)| f == fw) {
+                            info.errors.extend(
+                                compile_error(&format!(
+                                    "Duplicated firmware \"{}\" (entries {} and {}).",
+                                    fw,
+                                    first + 1,
+                                    i + 1
+                                ))
+                                .into_iter()
+                                .map(|mut tt| {
+                                    tt.set_span(*span);
+                                    tt
+                                }),
+                            );
+                        }
+                    }
+                    info.firmware = Some(firmware.into_iter().map(|(fw, _)| fw).collect());
+                }
+                "firmware_modular_only" => info.firmware_modular_only = expect_bool(it),
+                "strict" => info.strict = expect_bool(it),
+                "livepatch" => info.livepatch = expect_bool(it),
+                "scmversion" => info.scmversion = Some(expect_string_ascii(it)),
+                "abi_version" => {
+                    let version = expect_string_ascii(it);
+                    if version.is_empty()
+                        || !version
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b"._+-".contains(&b))
+                    {
+                        panic!("Invalid ABI version \"{}\".", version);
+                    }
+                    info.abi_version = Some(version);
+                }
+                "compression" => {
+                    let compression = expect_string_ascii(it);
+                    if !COMPRESSIONS.contains(&compression.as_str()) {
+                        panic!(
+                            "Invalid compression \"{}\", expected one of {:?}.",
+                            compression, COMPRESSIONS
+                        );
+                    }
+                    info.compression = Some(compression);
+                }
+                "info" => info.info = Some(expect_info_map(it)),
+                "module_type" => {
+                    let module_type = expect_string_ascii(it);
+                    if module_type.is_empty()
+                        || !module_type
+                            .bytes()
+                            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+                    {
+                        panic!("Invalid module type \"{}\".", module_type);
+                    }
+                    info.module_type = Some(module_type);
+                }
+                "initcall_level" => {
+                    let level = expect_string_ascii(it);
+                    if initcall_section(&level).is_none() {
+                        panic!("Unknown initcall level \"{}\".", level);
+                    }
+                    info.initcall_level = Some(level);
+                }
+                "init_symbol" => info.init_symbol = Some(expect_c_identifier(it)),
+                "exit_symbol" => info.exit_symbol = Some(expect_c_identifier(it)),
+                "data_section" => {
+                    let section = expect_string_ascii(it);
+                    if section.is_empty()
+                        || !section
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b"._$".contains(&b))
+                    {
+                        panic!("Invalid section name \"{}\".", section);
+                    }
+                    info.data_section = Some(section);
+                }
+                "test_stub" => info.test_stub = expect_bool(it),
                 _ => panic!(
                     "Unknown key \"{}\". Valid keys are: {:?}.",
                     key, EXPECTED_KEYS
@@ -176,6 +497,45 @@ fn parse(it: &mut token_stream::IntoIter) -> Self {
             );
         }
 
+        if info.module_type.is_some()
+            && info
+                .info
+                .as_ref()
+                .is_some_and(|entries| entries.iter().any(|(tag, _)| tag == "type"))
+        {
+            panic!("The `type` info tag is already given by `module_type`.");
+        }
+
+        if info.livepatch {
+            if info.initcall_level.is_some()
+                || info.init_symbol.is_some()
+                || info.exit_symbol.is_some()
+            {
+                panic!(
+                    "`initcall_level`, `init_symbol` and `exit_symbol` only apply to built-in \
+                    modules, but `livepatch` modules are always loadable."
+                );
+            }
+            if info.test_stub {
+                panic!("`livepatch` and `test_stub` cannot be combined.");
+            }
+            if info
+                .info
+                .as_ref()
+                .is_some_and(|entries| entries.iter().any(|(tag, _)| tag == "livepatch"))
+            {
+                panic!("The `livepatch` info tag is already given by `livepatch`.");
+            }
+        }
+
+        if info.data_section.is_some() && info.test_stub {
+            panic!("`data_section` and `test_stub` cannot be combined.");
+        }
+
+        if info.init_symbol.is_some() && info.init_symbol == info.exit_symbol {
+            panic!("`init_symbol` and `exit_symbol` must be different.");
+        }
+
         info
     }
 }
@@ -184,6 +544,7 @@ pub(crate) fn module(ts: TokenStream) -> TokenStream {
     let mut it = ts.into_iter();
 
     let info = ModuleInfo::parse(&mut it);
+    let errors = info.errors;
 
     let mut modinfo = ModInfoBuilder::new(info.name.as_ref());
     if let Some(author) = info.author {
@@ -198,23 +559,125 @@ pub(crate) fn module(ts: TokenStream) -> TokenStream {
         modinfo.emit("description", &description);
     }
     modinfo.emit("license", &info.license);
+    if let Some(license_files) = info.license_files {
+        for file in license_files {
+            modinfo.emit("license_file", &file);
+        }
+    }
+    if let Some(license_note) = info.license_note {
+        // Only the `license` tag is checked by the kernel to taint it, this one is informational.
+        modinfo.emit("license_note", &license_note);
+    }
     if let Some(aliases) = info.alias {
         for alias in aliases {
             modinfo.emit("alias", &alias);
         }
     }
+    if let Some(compatibles) = &info.of_match {
+        // The aliases that modpost generates for an `of_device_id` table matching only on the
+        // compatible, with and without further compatibles of the device following it.
+        for compatible in compatibles {
+            modinfo.emit("alias", &format!("of:N*T*C{compatible}"));
+            modinfo.emit("alias", &format!("of:N*T*C{compatible}C*"));
+        }
+    }
+    let has_builtin_firmware =
+        !info.firmware_modular_only && info.firmware.as_ref().is_some_and(|fw| !fw.is_empty());
     if let Some(firmware) = info.firmware {
         for fw in firmware {
-            modinfo.emit("firmware", &fw);
+            if info.firmware_modular_only {
+                modinfo.emit_only_loadable("firmware", &fw);
+            } else {
+                modinfo.emit("firmware", &fw);
+            }
         }
     }
+    if info.livepatch {
+        // Checked by the kernel when loading the module, like `MODULE_INFO(livepatch, "Y")`.
+        modinfo.emit_only_loadable("livepatch", "Y");
+    }
+    if let Some(scmversion) = info.scmversion {
+        modinfo.emit("scmversion", &scmversion);
+    }
+    if let Some(abi_version) = info.abi_version {
+        modinfo.emit("abi_version", &abi_version);
+    }
+    if let Some(compression) = info.compression {
+        // Built-in modules are part of the kernel image, so they are never compressed on their own.
+        modinfo.emit_only_loadable("compression", &compression);
+    }
+    if let Some(entries) = info.info {
+        for (tag, value) in entries {
+            modinfo.emit(&tag, &value);
+        }
+    }
+    if let Some(module_type) = info.module_type {
+        modinfo.emit("type", &module_type);
+    }
+
+    // Firmware of built-in modules is requested before the root filesystem may be mounted, hence
+    // it needs to be built into the kernel image or the initramfs as well, which is easy to miss.
+    let strict_check = if info.strict && has_builtin_firmware {
+        format!(
+            "
+                #[cfg(not(MODULE))]
+                ::core::compile_error!(
+                    \"Module \\\"{name}\\\" declares `firmware` but is built-in. Build it as a \\
+                    loadable module or set `firmware_modular_only: true`.\"
+                );
+            ",
+            name = info.name
+        )
+    } else {
+        String::new()
+    };
+
+    // The livepatch core patches functions of loaded code, it cannot apply a built-in patch.
+    let livepatch_check = if info.livepatch {
+        format!(
+            "
+                #[cfg(not(MODULE))]
+                ::core::compile_error!(
+                    \"Livepatch module \\\"{name}\\\" must be built as a loadable module.\"
+                );
+            ",
+            name = info.name
+        )
+    } else {
+        String::new()
+    };
+
+    let of_compatibles = match &info.of_match {
+        Some(compatibles) => format!(
+            "
+                /// The compatibles given in `of_match`, for the device table of the driver.
+                #[allow(dead_code)]
+                const OF_COMPATIBLES: &[&kernel::str::CStr] = &[{}];
+            ",
+            compatibles
+                .iter()
+                .map(|c| format!("kernel::c_str!(\"{c}\"),"))
+                .collect::<String>()
+        ),
+        None => String::new(),
+    };
+
+    // Checks that the type implements `Module` (or `InPlaceModule`) with an error pointing at the
+    // type, since the errors in the generated code below point at the whole macro invocation.
+    let mut type_check = Assertions::new([], []);
+    type_check.implements(info.type_tokens.clone(), "kernel::InPlaceModule");
+    let type_check = type_check.build();
+
+    if info.test_stub {
+        return TokenStream::from_iter([errors, type_check, test_stub(&info.type_, &info.name)]);
+    }
 
     // Built-in modules also export the `file` modinfo string.
     let file =
         std::env::var("RUST_MODFILE").expect("Unable to fetch RUST_MODFILE environmental variable");
     modinfo.emit_only_builtin("file", &file);
 
-    format!(
+    let glue: TokenStream = format!(
         "
             /// The module name.
             ///
@@ -240,6 +703,12 @@ pub(crate) fn module(ts: TokenStream) -> TokenStream {
             /// `module_pci_driver!`, `module_platform_driver!`, etc.
             type LocalModule = {type_};
 
+            {of_compatibles}
+
+            {strict_check}
+
+            {livepatch_check}
+
             impl kernel::ModuleMetadata for {type_} {{
                 const NAME: &'static kernel::str::CStr = kernel::c_str!(\"{name}\");
             }}
@@ -247,7 +716,7 @@ impl kernel::ModuleMetadata for {type_} {{
             // Double nested modules, since then nobody can access the public items inside.
             mod __module_init {{
                 mod __module_init {{
-                    use super::super::{type_};
+                    use super::super::LocalModule;
                     use kernel::init::PinInit;
 
                     /// The \"Rust loadable module\" mark.
@@ -259,7 +728,8 @@ mod __module_init {{
                     #[used]
                     static __IS_RUST_MODULE: () = ();
 
-                    static mut __MOD: core::mem::MaybeUninit<{type_}> =
+                    {data_section}
+                    static mut __MOD: core::mem::MaybeUninit<LocalModule> =
                         core::mem::MaybeUninit::uninit();
 
                     // Loadable modules need to export the `{{init,cleanup}}_module` identifiers.
@@ -310,14 +780,14 @@ mod __module_init {{
                     #[doc(hidden)]
                     #[link_section = \"{initcall_section}\"]
                     #[used]
-                    pub static __{name}_initcall: extern \"C\" fn() -> kernel::ffi::c_int = __{name}_init;
+                    pub static __{name}_initcall: extern \"C\" fn() -> kernel::ffi::c_int = {init_symbol};
 
                     #[cfg(not(MODULE))]
                     #[cfg(CONFIG_HAVE_ARCH_PREL32_RELOCATIONS)]
                     core::arch::global_asm!(
                         r#\".section \"{initcall_section}\", \"a\"
                         __{name}_initcall:
-                            .long   __{name}_init - .
+                            .long   {init_symbol} - .
                             .previous
                         \"#
                     );
@@ -325,7 +795,7 @@ mod __module_init {{
                     #[cfg(not(MODULE))]
                     #[doc(hidden)]
                     #[no_mangle]
-                    pub extern \"C\" fn __{name}_init() -> kernel::ffi::c_int {{
+                    pub extern \"C\" fn {init_symbol}() -> kernel::ffi::c_int {{
                         // SAFETY: This function is inaccessible to the outside due to the double
                         // module wrapping it. It is called exactly once by the C side via its
                         // placement above in the initcall section.
@@ -335,12 +805,12 @@ mod __module_init {{
                     #[cfg(not(MODULE))]
                     #[doc(hidden)]
                     #[no_mangle]
-                    pub extern \"C\" fn __{name}_exit() {{
+                    pub extern \"C\" fn {exit_symbol}() {{
                         // SAFETY:
                         // - This function is inaccessible to the outside due to the double
                         //   module wrapping it. It is called exactly once by the C side via its
                         //   unique name,
-                        // - furthermore it is only called after `__{name}_init` has returned `0`
+                        // - furthermore it is only called after `{init_symbol}` has returned `0`
                         //   (which delegates to `__init`).
                         unsafe {{ __exit() }}
                     }}
@@ -350,7 +820,7 @@ mod __module_init {{
                     /// This function must only be called once.
                     unsafe fn __init() -> kernel::ffi::c_int {{
                         let initer =
-                            <{type_} as kernel::InPlaceModule>::init(&super::super::THIS_MODULE);
+                            <LocalModule as kernel::InPlaceModule>::init(&super::super::THIS_MODULE);
                         // SAFETY: No data race, since `__MOD` can only be accessed by this module
                         // and there only `__init` and `__exit` access it. These functions are only
                         // called once and `__exit` cannot be called before or during `__init`.
@@ -382,7 +852,99 @@ unsafe fn __exit() {{
         type_ = info.type_,
         name = info.name,
         modinfo = modinfo.buffer,
-        initcall_section = ".initcall6.init"
+        of_compatibles = of_compatibles,
+        strict_check = strict_check,
+        livepatch_check = livepatch_check,
+        data_section = info
+            .data_section
+            .map(|section| format!("#[link_section = \"{section}\"]"))
+            .unwrap_or_default(),
+        initcall_section = initcall_section(info.initcall_level.as_deref().unwrap_or("device"))
+            .unwrap(),
+        init_symbol = info
+            .init_symbol
+            .unwrap_or_else(|| format!("__{}_init", info.name)),
+        exit_symbol = info
+            .exit_symbol
+            .unwrap_or_else(|| format!("__{}_exit", info.name)),
+    )
+    .parse()
+    .expect("Error parsing formatted string into token stream.");
+
+    TokenStream::from_iter([errors, type_check, glue])
+}
+
+/// Generates the minimal glue for host tests of the `init` and exit logic of a kernel module.
+///
+/// No modinfo, initcall or `{init,cleanup}_module` symbols are emitted, so the result can be
+/// linked into a host test binary, but not loaded into a kernel.
+fn test_stub(type_: &str, name: &str) -> TokenStream {
+    format!(
+        "
+            #[cfg(not(test))]
+            ::core::compile_error!(
+                \"`test_stub` can only be used in test builds, it does not produce a loadable or \\
+                built-in kernel module.\"
+            );
+
+            /// The module name.
+            ///
+            /// Used by the printing macros, e.g. [`info!`].
+            const __LOG_PREFIX: &[u8] = b\"{name}\\0\";
+
+            // There is no `struct module` in host tests.
+            static THIS_MODULE: kernel::ThisModule = unsafe {{
+                kernel::ThisModule::from_ptr(core::ptr::null_mut())
+            }};
+
+            /// The `LocalModule` type is the type of the module created by `module!`,
+            /// `module_pci_driver!`, `module_platform_driver!`, etc.
+            type LocalModule = {type_};
+
+            impl kernel::ModuleMetadata for {type_} {{
+                const NAME: &'static kernel::str::CStr = kernel::c_str!(\"{name}\");
+            }}
+
+            /// Runs the `init` and exit logic of the module in host tests.
+            #[doc(hidden)]
+            mod __module_test_stub {{
+                use super::LocalModule;
+                use kernel::init::PinInit;
+
+                static mut __MOD: core::mem::MaybeUninit<LocalModule> =
+                    core::mem::MaybeUninit::uninit();
+
+                /// Initializes the module like loading it would.
+                ///
+                /// # Safety
+                ///
+                /// This function must only be called once.
+                pub(crate) unsafe fn init() -> kernel::ffi::c_int {{
+                    let initer = <LocalModule as kernel::InPlaceModule>::init(&super::THIS_MODULE);
+                    // SAFETY: No data race, since `__MOD` can only be accessed by `init` and
+                    // `exit`, which are only called once, and `exit` not before `init`.
+                    match unsafe {{ initer.__pinned_init(__MOD.as_mut_ptr()) }} {{
+                        Ok(m) => 0,
+                        Err(e) => e.to_errno(),
+                    }}
+                }}
+
+                /// Drops the module like unloading it would.
+                ///
+                /// # Safety
+                ///
+                /// This function must
+                /// - only be called once,
+                /// - be called after `init` has been called and returned `0`.
+                pub(crate) unsafe fn exit() {{
+                    // SAFETY: No data race, since `__MOD` can only be accessed by `init` and
+                    // `exit`, which are only called once, and `init` was already called.
+                    unsafe {{
+                        __MOD.assume_init_drop();
+                    }}
+                }}
+            }}
+        "
     )
     .parse()
     .expect("Error parsing formatted string into token stream.")
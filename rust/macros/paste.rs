@@ -1,85 +1,515 @@
 // SPDX-License-Identifier: GPL-2.0
 
-use proc_macro::{Delimiter, Group, Ident, Spacing, Span, TokenTree};
+use proc_macro::{Delimiter, Group, Ident, Literal, Spacing, Span, TokenStream, TokenTree};
 
-fn concat_helper(tokens: &[TokenTree]) -> Vec<(String, Span)> {
+/// The kind of token a paste segment originates from.
+///
+/// Modifiers are only meaningful for some kinds, e.g. changing the case of an integer literal
+/// would turn `0x1f` into `0X1F`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SegmentKind {
+    Ident,
+    Str,
+    Int,
+    Literal,
+}
+
+impl SegmentKind {
+    fn describe(self) -> &'static str {
+        match self {
+            SegmentKind::Ident => "an identifier",
+            SegmentKind::Str => "a string literal",
+            SegmentKind::Int => "an integer literal",
+            SegmentKind::Literal => "a literal",
+        }
+    }
+}
+
+/// Returns the segment kinds `modifier` can be applied to, `None` if it applies to any kind.
+fn modifier_kinds(modifier: &str) -> Option<&'static [SegmentKind]> {
+    match modifier {
+        "lower" | "upper" | "replace" => Some(&[SegmentKind::Ident, SegmentKind::Str]),
+        "reverse" => Some(&[SegmentKind::Ident, SegmentKind::Str, SegmentKind::Int]),
+        "pad" | "ordinal" => Some(&[SegmentKind::Int]),
+        _ => None,
+    }
+}
+
+/// An error in the paste segments, reported with `compile_error!` at `span`.
+pub(crate) struct Error {
+    msg: String,
+    span: Span,
+}
+
+impl Error {
+    fn new(msg: String, span: Span) -> Self {
+        Error { msg, span }
+    }
+
+    pub(crate) fn into_compile_error(self) -> TokenStream {
+        let mut msg = Literal::string(&self.msg);
+        msg.set_span(self.span);
+        let mut body = Group::new(Delimiter::Brace, TokenStream::from(TokenTree::Literal(msg)));
+        body.set_span(self.span);
+        let tokens: TokenStream = "::core::compile_error!".parse().unwrap();
+        tokens
+            .into_iter()
+            .map(|mut tok| {
+                tok.set_span(self.span);
+                tok
+            })
+            .chain([TokenTree::Group(body)])
+            .collect()
+    }
+}
+
+/// Returns the arguments of a modifier.
+fn modifier_args(args: &Group) -> Vec<TokenTree> {
+    // Arguments might be passed through `macro_rules!` fragments.
+    args.stream()
+        .into_iter()
+        .flat_map(|tt| match tt {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::None => {
+                group.stream().into_iter().collect()
+            }
+            tt => vec![tt],
+        })
+        .collect()
+}
+
+const PAD_USAGE: &str = "`pad` modifier expects a width and a fill character, e.g. `:pad(3, '0')`";
+
+/// Parses the `(width, 'c')` arguments of the `pad` modifier.
+fn pad_args(args: &Group) -> Result<(usize, char), Error> {
+    let err = || Error::new(PAD_USAGE.to_string(), args.span());
+    let tokens = modifier_args(args);
+    let [TokenTree::Literal(width), TokenTree::Punct(comma), TokenTree::Literal(fill)] =
+        &tokens[..]
+    else {
+        return Err(err());
+    };
+    if comma.as_char() != ',' {
+        return Err(err());
+    }
+    let width = width.to_string().parse::<usize>().map_err(|_| err())?;
+    let fill = fill.to_string();
+    let mut fill = fill
+        .strip_prefix('\'')
+        .and_then(|f| f.strip_suffix('\''))
+        .ok_or_else(err)?
+        .chars();
+    match (fill.next(), fill.next()) {
+        (Some(c), None) => Ok((width, c)),
+        _ => Err(err()),
+    }
+}
+
+/// The English ordinal words of the numbers supported by the `ordinal` modifier.
+const ORDINALS: &[&str] = &[
+    "zeroth",
+    "first",
+    "second",
+    "third",
+    "fourth",
+    "fifth",
+    "sixth",
+    "seventh",
+    "eighth",
+    "ninth",
+    "tenth",
+    "eleventh",
+    "twelfth",
+    "thirteenth",
+    "fourteenth",
+    "fifteenth",
+    "sixteenth",
+    "seventeenth",
+    "eighteenth",
+    "nineteenth",
+    "twentieth",
+];
+
+const REPLACE_USAGE: &str =
+    "`replace` modifier expects two string literals, e.g. `:replace(\"__\", \"_\")`";
+
+/// Parses the `("from", "to")` arguments of the `replace` modifier.
+fn replace_args(args: &Group) -> Result<(String, String), Error> {
+    let err = || Error::new(REPLACE_USAGE.to_string(), args.span());
+    let string = |tt: &TokenTree| match tt {
+        TokenTree::Literal(lit) => lit
+            .to_string()
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .map(str::to_string),
+        _ => None,
+    };
+    let tokens = modifier_args(args);
+    let [from, TokenTree::Punct(comma), to] = &tokens[..] else {
+        return Err(err());
+    };
+    if comma.as_char() != ',' {
+        return Err(err());
+    }
+    match (string(from), string(to)) {
+        (Some(from), Some(to)) if !from.is_empty() => Ok((from, to)),
+        _ => Err(err()),
+    }
+}
+
+/// Concatenates the segments of a `[< >]` group.
+///
+/// `index` is the value of the next `#index` segment of the `paste!` invocation. `span` is set to
+/// the span of the segment with a `span` or `span_only` modifier, and whether it is used for the
+/// pasted identifier, which is only the case for `span_only`.
+fn concat_helper(
+    tokens: &[TokenTree],
+    span: &mut Option<(Span, bool)>,
+    index: &mut usize,
+) -> Result<Vec<(String, Span, SegmentKind)>, Error> {
     let mut tokens = tokens.iter();
     let mut segments = Vec::new();
-    let mut span = None;
     loop {
         match tokens.next() {
             None => break,
             Some(TokenTree::Literal(lit)) => {
                 // Allow us to concat string literals by stripping quotes
                 let mut value = lit.to_string();
-                if value.starts_with('"') && value.ends_with('"') {
+                let kind = if value.starts_with('"') && value.ends_with('"') {
                     value.remove(0);
                     value.pop();
-                }
-                segments.push((value, lit.span()));
+                    SegmentKind::Str
+                } else if value.starts_with(|c: char| c.is_ascii_digit()) && !value.contains('.') {
+                    SegmentKind::Int
+                } else {
+                    SegmentKind::Literal
+                };
+                segments.push((value, lit.span(), kind));
+            }
+            // `$crate` of a `macro_rules!` is an identifier, but not one that can be pasted. It is
+            // kept as-is outside of `[< >]`, e.g. in `$crate::[<Foo $n>]`.
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "$crate" => {
+                return Err(Error::new(
+                    "`$crate` cannot be pasted, it has to precede the `[< >]` group as in \
+                        `$crate::[<...>]`"
+                        .to_string(),
+                    ident.span(),
+                ))
             }
             Some(TokenTree::Ident(ident)) => {
                 let mut value = ident.to_string();
                 if value.starts_with("r#") {
                     value.replace_range(0..2, "");
                 }
-                segments.push((value, ident.span()));
+                segments.push((value, ident.span(), SegmentKind::Ident));
+            }
+            // Each `#index` is replaced by the number of `#index` segments before it.
+            Some(TokenTree::Punct(p)) if p.as_char() == '#' => {
+                match tokens.next() {
+                    Some(TokenTree::Ident(ident)) if ident.to_string() == "index" => {}
+                    _ => {
+                        return Err(Error::new(
+                            "expected `index` after `#`".to_string(),
+                            p.span(),
+                        ))
+                    }
+                }
+                segments.push((index.to_string(), p.span(), SegmentKind::Int));
+                *index += 1;
             }
             Some(TokenTree::Punct(p)) if p.as_char() == ':' => {
                 let Some(TokenTree::Ident(ident)) = tokens.next() else {
-                    panic!("expected identifier as modifier");
+                    return Err(Error::new(
+                        "expected identifier as modifier".to_string(),
+                        p.span(),
+                    ));
                 };
 
-                let (mut value, sp) = segments.pop().expect("expected identifier before modifier");
-                match ident.to_string().as_str() {
+                let Some((mut value, sp, mut kind)) = segments.pop() else {
+                    return Err(Error::new(
+                        "expected identifier before modifier".to_string(),
+                        p.span(),
+                    ));
+                };
+                let modifier = ident.to_string();
+                if modifier_kinds(&modifier).is_some_and(|kinds| !kinds.contains(&kind)) {
+                    return Err(Error::new(
+                        format!(
+                            "`{modifier}` modifier cannot be applied to {} (`{value}`)",
+                            kind.describe()
+                        ),
+                        sp,
+                    ));
+                }
+                match modifier.as_str() {
                     // Set the overall span of concatenated token as current span
-                    "span" => {
-                        assert!(
-                            span.is_none(),
-                            "span modifier should only appear at most once"
-                        );
-                        span = Some(sp);
+                    "span" | "span_only" => {
+                        if span.is_some() {
+                            return Err(Error::new(
+                                "span modifier should only appear at most once".to_string(),
+                                ident.span(),
+                            ));
+                        }
+                        // `span` keeps the span of the group, as it always has, since using the span
+                        // of the segment would change the hygiene of existing identifiers.
+                        *span = Some((sp, modifier == "span_only"));
+                        // The segment only serves as an anchor for the span.
+                        if modifier == "span_only" {
+                            value.clear();
+                        }
                     }
                     "lower" => value = value.to_lowercase(),
                     "upper" => value = value.to_uppercase(),
-                    v => panic!("unknown modifier `{v}`"),
+                    // Reversed by `char`, so multi-byte characters stay intact.
+                    "reverse" => value = value.chars().rev().collect(),
+                    "pad" => {
+                        let (width, fill) = match tokens.next() {
+                            Some(TokenTree::Group(args))
+                                if args.delimiter() == Delimiter::Parenthesis =>
+                            {
+                                pad_args(args)?
+                            }
+                            _ => return Err(Error::new(PAD_USAGE.to_string(), ident.span())),
+                        };
+                        // Numbers longer than the width are kept as they are.
+                        let len = value.chars().count();
+                        if len < width {
+                            value.insert_str(0, &fill.to_string().repeat(width - len));
+                        }
+                    }
+                    "ordinal" => {
+                        let Some(word) = value.parse::<usize>().ok().and_then(|n| ORDINALS.get(n))
+                        else {
+                            return Err(Error::new(
+                                format!(
+                                    "`ordinal` modifier only supports the numbers from 0 to {}, \
+                                        found `{value}`",
+                                    ORDINALS.len() - 1
+                                ),
+                                sp,
+                            ));
+                        };
+                        value = word.to_string();
+                        // The word can be modified like an identifier, e.g. by `upper`.
+                        kind = SegmentKind::Ident;
+                    }
+                    "replace" => {
+                        let (from, to) = match tokens.next() {
+                            Some(TokenTree::Group(args))
+                                if args.delimiter() == Delimiter::Parenthesis =>
+                            {
+                                replace_args(args)?
+                            }
+                            _ => return Err(Error::new(REPLACE_USAGE.to_string(), ident.span())),
+                        };
+                        value = value.replace(&from, &to);
+                    }
+                    "bstr" => {
+                        return Err(Error::new(
+                            "`bstr` modifier applies to the whole pasted text, so it must come \
+                                last"
+                                .to_string(),
+                            ident.span(),
+                        ))
+                    }
+                    v => return Err(Error::new(format!("unknown modifier `{v}`"), ident.span())),
                 };
-                segments.push((value, sp));
+                segments.push((value, sp, kind));
             }
             Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::None => {
                 let tokens = group.stream().into_iter().collect::<Vec<TokenTree>>();
-                segments.append(&mut concat_helper(tokens.as_slice()));
+                segments.append(&mut concat_helper(tokens.as_slice(), span, index)?);
+            }
+            Some(token) => {
+                return Err(Error::new(
+                    format!(
+                        "unexpected token `{token}` in paste segments, expected identifier, \
+                            literal or modifier"
+                    ),
+                    token.span(),
+                ))
             }
-            token => panic!("unexpected token in paste segments: {:?}", token),
         };
     }
 
-    segments
+    Ok(segments)
 }
 
-fn concat(tokens: &[TokenTree], group_span: Span) -> TokenTree {
-    let segments = concat_helper(tokens);
+fn concat(
+    tokens: &[TokenTree],
+    group_span: Span,
+    index: &mut usize,
+    max_len: Option<usize>,
+) -> Result<TokenTree, Error> {
+    // A trailing `:bstr` applies to the whole pasted text, so it is handled here.
+    let (tokens, bstr) = match tokens {
+        [rest @ .., TokenTree::Punct(p), TokenTree::Ident(i)]
+            if p.as_char() == ':' && i.to_string() == "bstr" =>
+        {
+            (rest, true)
+        }
+        _ => (tokens, false),
+    };
+    let mut span = None;
+    let segments = concat_helper(tokens, &mut span, index)?;
     let pasted: String = segments.into_iter().map(|x| x.0).collect();
-    TokenTree::Ident(Ident::new(&pasted, group_span))
+    let span = match span {
+        Some((span, true)) => span,
+        _ => group_span,
+    };
+    if bstr {
+        if !pasted.is_ascii() {
+            return Err(Error::new(
+                format!("`bstr` modifier requires ASCII text, but `{pasted}` is not"),
+                group_span,
+            ));
+        }
+        let mut literal = Literal::byte_string(pasted.as_bytes());
+        literal.set_span(span);
+        return Ok(TokenTree::Literal(literal));
+    }
+    if pasted.is_empty() {
+        return Err(Error::new(
+            "pasted identifier is empty".to_string(),
+            group_span,
+        ));
+    }
+    let len = pasted.chars().count();
+    if let Some(max_len) = max_len.filter(|&max_len| len > max_len) {
+        return Err(Error::new(
+            format!(
+                "pasted identifier is {len} characters long, which exceeds the maximum of {max_len}"
+            ),
+            group_span,
+        ));
+    }
+    // Identifiers cannot start with a digit, so this must be a numeric literal.
+    if pasted.starts_with(|c: char| c.is_ascii_digit()) {
+        let mut literal: Literal = pasted
+            .parse()
+            .map_err(|_| Error::new(format!("`{pasted}` is not a valid numeric literal"), span))?;
+        literal.set_span(span);
+        return Ok(TokenTree::Literal(literal));
+    }
+    Ok(TokenTree::Ident(Ident::new(&pasted, span)))
+}
+
+/// Returns whether a group looks like `[< A B C D >]`.
+fn is_paste_group(delimiter: Delimiter, stream: &[TokenTree]) -> bool {
+    delimiter == Delimiter::Bracket
+        && stream.len() >= 3
+        && matches!(&stream[0], TokenTree::Punct(p) if p.as_char() == '<')
+        && matches!(&stream[stream.len() - 1], TokenTree::Punct(p) if p.as_char() == '>')
+}
+
+/// Returns whether the group is an escaped `[< >]` group, i.e. `[<< >>]`.
+///
+/// A `<` cannot start a segment, so this is not a valid `[< >]` group otherwise.
+fn is_escaped_paste_group(delimiter: Delimiter, stream: &[TokenTree]) -> bool {
+    is_paste_group(delimiter, stream)
+        && stream.len() >= 4
+        && matches!(&stream[1], TokenTree::Punct(p) if p.as_char() == '<')
+        && matches!(&stream[stream.len() - 2], TokenTree::Punct(p) if p.as_char() == '>')
+}
+
+fn contains_paste_group(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Group(group) => {
+            let stream: Vec<_> = group.stream().into_iter().collect();
+            is_paste_group(group.delimiter(), &stream) || contains_paste_group(group.stream())
+        }
+        _ => false,
+    })
 }
 
-pub(crate) fn expand(tokens: &mut Vec<TokenTree>) {
+/// The default maximum length of pasted identifiers with `#![max_len]`.
+///
+/// This is far above what is reasonable for a name, but below the limits of the kernel, e.g.
+/// `KSYM_NAME_LEN`.
+const DEFAULT_MAX_LEN: usize = 255;
+
+/// The options given by the leading markers of a `paste!` invocation.
+#[derive(Default)]
+pub(crate) struct Options {
+    /// Whether it is an error if there is nothing to paste, set by `#![strict]`.
+    strict: bool,
+    /// The maximum length of pasted identifiers, set by `#![max_len]` or `#![max_len = N]`.
+    pub(crate) max_len: Option<usize>,
+}
+
+/// Handles the optional leading `#![strict]` and `#![max_len]` markers by removing them from
+/// `tokens`.
+///
+/// With `#![strict]`, it is an error if there is nothing to paste. This is opt-in, since macros
+/// may expand to `paste!` invocations that only sometimes contain `[< >]` groups.
+pub(crate) fn parse_options(tokens: &mut Vec<TokenTree>) -> Result<Options, Error> {
+    let mut options = Options::default();
+    let mut strict_span = None;
+    while let [TokenTree::Punct(hash), TokenTree::Punct(bang), TokenTree::Group(attr), ..] =
+        &tokens[..]
+    {
+        if hash.as_char() != '#' || bang.as_char() != '!' || attr.delimiter() != Delimiter::Bracket
+        {
+            break;
+        }
+        let marker: Vec<_> = attr.stream().into_iter().collect();
+        match &marker[..] {
+            [TokenTree::Ident(i)] if i.to_string() == "strict" => {
+                options.strict = true;
+                strict_span = Some(attr.span());
+            }
+            [TokenTree::Ident(i)] if i.to_string() == "max_len" => {
+                options.max_len = Some(DEFAULT_MAX_LEN);
+            }
+            [TokenTree::Ident(i), TokenTree::Punct(eq), TokenTree::Literal(lit)]
+                if i.to_string() == "max_len" && eq.as_char() == '=' =>
+            {
+                let max_len = lit.to_string().parse().map_err(|_| {
+                    Error::new(
+                        "expected an integer, e.g. `#![max_len = 64]`".to_string(),
+                        lit.span(),
+                    )
+                })?;
+                options.max_len = Some(max_len);
+            }
+            _ => break,
+        }
+        tokens.drain(..3);
+    }
+    if let Some(span) = strict_span {
+        if !contains_paste_group(tokens.iter().cloned().collect()) {
+            return Err(Error::new(
+                "`paste!` does not contain any `[< >]` groups, remove it".to_string(),
+                span,
+            ));
+        }
+    }
+    Ok(options)
+}
+
+pub(crate) fn expand(
+    tokens: &mut Vec<TokenTree>,
+    index: &mut usize,
+    max_len: Option<usize>,
+) -> Result<(), Error> {
     for token in tokens.iter_mut() {
         if let TokenTree::Group(group) = token {
             let delimiter = group.delimiter();
             let span = group.span();
             let mut stream: Vec<_> = group.stream().into_iter().collect();
-            // Find groups that looks like `[< A B C D >]`
-            if delimiter == Delimiter::Bracket
-                && stream.len() >= 3
-                && matches!(&stream[0], TokenTree::Punct(p) if p.as_char() == '<')
-                && matches!(&stream[stream.len() - 1], TokenTree::Punct(p) if p.as_char() == '>')
-            {
+            if is_escaped_paste_group(delimiter, &stream) {
+                // Emit the `[< >]` group as-is, without expanding it.
+                let mut group = Group::new(
+                    delimiter,
+                    stream[1..stream.len() - 1].iter().cloned().collect(),
+                );
+                group.set_span(span);
+                *token = TokenTree::Group(group);
+            } else if is_paste_group(delimiter, &stream) {
                 // Replace the group with concatenated token
-                *token = concat(&stream[1..stream.len() - 1], span);
+                *token = concat(&stream[1..stream.len() - 1], span, index, max_len)?;
             } else {
                 // Recursively expand tokens inside the group
-                expand(&mut stream);
+                expand(&mut stream, index, max_len)?;
                 let mut group = Group::new(delimiter, stream.into_iter().collect());
                 group.set_span(span);
                 *token = TokenTree::Group(group);
@@ -110,4 +540,6 @@ pub(crate) fn expand(tokens: &mut Vec<TokenTree>) {
             }
         }
     }
+
+    Ok(())
 }
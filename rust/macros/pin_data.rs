@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use crate::helpers::{parse_generics, Generics};
-use proc_macro::{Group, Punct, Spacing, TokenStream, TokenTree};
+use proc_macro::{Delimiter, Group, Punct, Spacing, TokenStream, TokenTree};
 
 pub(crate) fn pin_data(args: TokenStream, input: TokenStream) -> TokenStream {
     // This proc-macro only does some pre-parsing and then delegates the actual parsing to
@@ -69,8 +69,34 @@ pub(crate) fn pin_data(args: TokenStream, input: TokenStream) -> TokenStream {
             }
         })
         .collect::<Vec<_>>();
-    // This should be the body of the struct `{...}`.
-    let last = rest.pop();
+    // This should be the body of the struct `{...}`. The attributes of the struct, e.g.
+    // `#[repr(C)]`, stay in `rest` and are passed through unchanged.
+    let mut last = rest.pop();
+    // A tuple struct ends with `;` instead, its fields are in a `(...)` group after the name.
+    // `pin_init!` initializes fields by their name, so their index cannot be used.
+    if matches!(&last, Some(TokenTree::Punct(p)) if p.as_char() == ';') {
+        if let Some(fields) = tuple_fields(&rest) {
+            return "::core::compile_error!(\"`#[pin_data]` cannot be used on tuple structs, since \
+                `pin_init!` initializes fields by their name. Use a struct with named fields \
+                instead.\");"
+                .parse::<TokenStream>()
+                .unwrap()
+                .into_iter()
+                .map(|mut tok| {
+                    tok.set_span(fields.span());
+                    tok
+                })
+                .collect();
+        }
+    }
+    if let Some(TokenTree::Group(body)) = &mut last {
+        *body = expand_cfg_pin(body);
+        for field in parse_fields(body) {
+            if field.pinned {
+                deny_smart_pointer(&field.ty, &mut errs);
+            }
+        }
+    }
     let mut quoted = quote!(::kernel::__pin_data! {
         parse_input:
         @args(#args),
@@ -84,6 +110,18 @@ pub(crate) fn pin_data(args: TokenStream, input: TokenStream) -> TokenStream {
     quoted
 }
 
+/// Returns the fields of a tuple struct, i.e. the `(...)` group following the name, if `rest` is
+/// one.
+fn tuple_fields(rest: &[TokenTree]) -> Option<&Group> {
+    let pos = rest
+        .iter()
+        .position(|tt| matches!(tt, TokenTree::Ident(i) if i.to_string() == "struct"))?;
+    match rest.get(pos + 2) {
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis => Some(g),
+        _ => None,
+    }
+}
+
 /// Replaces `Self` with `struct_name` and errors on `enum`, `trait`, `struct` `union` and `impl`
 /// keywords.
 ///
@@ -127,3 +165,184 @@ fn replace_self_and_deny_type_defs(
         ))],
     }
 }
+
+/// A field of the struct, as far as `#[pin_data]` needs to know about it.
+struct Field {
+    /// Whether the field is annotated with `#[pin]`.
+    pinned: bool,
+    /// The type of the field.
+    ty: Vec<TokenTree>,
+}
+
+/// Splits the body of a struct into the tokens of its fields.
+fn split_fields(body: &Group) -> Vec<Vec<TokenTree>> {
+    let mut fields = Vec::new();
+    let mut field = Vec::new();
+    // The current level of `<`.
+    let mut nesting = 0;
+    let mut prev_dash = false;
+    for tt in body.stream() {
+        match &tt {
+            TokenTree::Punct(p) if p.as_char() == ',' && nesting == 0 => {
+                fields.push(std::mem::take(&mut field));
+                prev_dash = false;
+                continue;
+            }
+            TokenTree::Punct(p) if p.as_char() == '<' => nesting += 1,
+            // The `>` of a `->` in a function pointer type does not close a `<`.
+            TokenTree::Punct(p) if p.as_char() == '>' && !prev_dash => nesting -= 1,
+            _ => {}
+        }
+        prev_dash = matches!(&tt, TokenTree::Punct(p) if p.as_char() == '-');
+        field.push(tt);
+    }
+    if !field.is_empty() {
+        fields.push(field);
+    }
+    fields
+}
+
+/// Returns the predicate `pred` of a `cfg_attr(pred, pin)` attribute.
+fn cfg_pin_predicate(attr: &Group) -> Option<TokenStream> {
+    let mut toks = attr.stream().into_iter();
+    match (toks.next(), toks.next(), toks.next()) {
+        (Some(TokenTree::Ident(i)), Some(TokenTree::Group(args)), None)
+            if i.to_string() == "cfg_attr" && args.delimiter() == Delimiter::Parenthesis =>
+        {
+            let mut args = args.stream().into_iter().collect::<Vec<_>>();
+            match args.as_slice() {
+                [.., TokenTree::Punct(p), TokenTree::Ident(pin)]
+                    if p.as_char() == ',' && pin.to_string() == "pin" =>
+                {
+                    args.truncate(args.len() - 2);
+                    Some(args.into_iter().collect())
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Rewrites fields marked with `#[cfg_attr(pred, pin)]` into two fields with the same name, one
+/// with `#[cfg(pred)] #[pin]` and one with `#[cfg(not(pred))]`.
+///
+/// `__pin_data!` only recognizes a literal `#[pin]`, but it forwards `#[cfg]` attributes of
+/// fields to the code it generates for them. This way the projection of the field is pinned in
+/// exactly the configurations in which the field is.
+fn expand_cfg_pin(body: &Group) -> Group {
+    let mut stream = TokenStream::new();
+    for field in split_fields(body) {
+        let mut pred = None;
+        let mut rest = Vec::new();
+        let mut toks = field.into_iter().peekable();
+        while matches!(toks.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '#') {
+            let pound = toks.next().unwrap();
+            match toks.next() {
+                Some(TokenTree::Group(attr)) if pred.is_none() => {
+                    pred = cfg_pin_predicate(&attr);
+                    if pred.is_none() {
+                        rest.extend([pound, TokenTree::Group(attr)]);
+                    }
+                }
+                attr => rest.extend([pound].into_iter().chain(attr)),
+            }
+        }
+        rest.extend(toks);
+        let rest = rest.into_iter().collect::<TokenStream>();
+        match pred {
+            Some(pred) => stream.extend(quote! {
+                #[cfg(#pred)]
+                #[pin]
+                #rest,
+                #[cfg(not(#pred))]
+                #rest,
+            }),
+            None => {
+                stream.extend(rest);
+                stream.extend([TokenTree::Punct(Punct::new(',', Spacing::Alone))]);
+            }
+        }
+    }
+    let mut group = Group::new(body.delimiter(), stream);
+    group.set_span(body.span());
+    group
+}
+
+/// Splits the body of a struct into its fields.
+fn parse_fields(body: &Group) -> Vec<Field> {
+    split_fields(body)
+        .into_iter()
+        .map(|field| {
+            let mut pinned = false;
+            let mut toks = field.into_iter().peekable();
+            // Attributes come first.
+            while matches!(toks.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '#') {
+                toks.next();
+                if let Some(TokenTree::Group(attr)) = toks.next() {
+                    pinned |= attr.delimiter() == Delimiter::Bracket
+                        && attr.stream().to_string() == "pin";
+                }
+            }
+            // Skip the visibility and the name up to the `:` separating the type.
+            let mut prev_colon = false;
+            let mut ty = Vec::new();
+            let mut in_ty = false;
+            for tt in toks {
+                if in_ty {
+                    ty.push(tt);
+                    continue;
+                }
+                match &tt {
+                    TokenTree::Punct(p) if p.as_char() == ':' && p.spacing() == Spacing::Joint => {
+                        prev_colon = true;
+                    }
+                    TokenTree::Punct(p) if p.as_char() == ':' && !prev_colon => in_ty = true,
+                    _ => prev_colon = false,
+                }
+            }
+            Field { pinned, ty }
+        })
+        .collect()
+}
+
+/// Errors on `#[pin]` fields whose type is a smart pointer.
+///
+/// Smart pointers such as `KBox<T>` are `Unpin` and do not structurally pin their contents, so
+/// `#[pin]` on such a field would not pin `T`. A `Pin<KBox<T>>` field must be used instead.
+///
+/// The error is appended to `errs` to allow normal parsing to continue.
+fn deny_smart_pointer(ty: &[TokenTree], errs: &mut TokenStream) {
+    // The last path segment before the generic arguments names the type.
+    let Some(name) = ty
+        .iter()
+        .take_while(|tt| !matches!(tt, TokenTree::Punct(p) if p.as_char() == '<'))
+        .filter_map(|tt| match tt {
+            TokenTree::Ident(i) => Some(i),
+            _ => None,
+        })
+        .last()
+    else {
+        return;
+    };
+    let name_str = name.to_string();
+    if !matches!(
+        name_str.as_str(),
+        "Box" | "KBox" | "VBox" | "KVBox" | "Arc" | "UniqueArc"
+    ) {
+        return;
+    }
+    errs.extend(
+        format!(
+            "::core::compile_error!(\"`#[pin]` does not pin the contents of a `{name_str}`, use a \
+                `Pin<{name_str}<T>>` field without `#[pin]` instead.\");"
+        )
+        .parse::<TokenStream>()
+        .unwrap()
+        .into_iter()
+        .map(|mut tok| {
+            tok.set_span(name.span());
+            tok
+        }),
+    );
+}
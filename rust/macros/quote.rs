@@ -44,6 +44,9 @@ fn to_tokens(&self, tokens: &mut TokenStream) {
 /// This is a similar to the
 /// [`quote_spanned!`](https://docs.rs/quote/latest/quote/macro.quote_spanned.html) macro from the
 /// `quote` crate but provides only just enough functionality needed by the current `macros` crate.
+///
+/// A literal `#` that would otherwise be interpreted as an interpolation can be emitted with `##`,
+/// e.g. `##name` emits `#name`.
 macro_rules! quote_spanned {
     ($span:expr => $($tt:tt)*) => {{
         let mut tokens: ::std::vec::Vec<::proc_macro::TokenTree>;
@@ -56,6 +59,12 @@ macro_rules! quote_spanned {
         ::proc_macro::TokenStream::from_iter(tokens)
     }};
     (@proc $v:ident $span:ident) => {};
+    (@proc $v:ident $span:ident # # $($tt:tt)*) => {
+        $v.push(::proc_macro::TokenTree::Punct(
+            ::proc_macro::Punct::new('#', ::proc_macro::Spacing::Alone)
+        ));
+        quote_spanned!(@proc $v $span $($tt)*);
+    };
     (@proc $v:ident $span:ident #$id:ident $($tt:tt)*) => {
         let mut ts = ::proc_macro::TokenStream::new();
         $crate::quote::ToTokens::to_tokens(&$id, &mut ts);
@@ -81,7 +90,8 @@ macro_rules! quote_spanned {
         quote_spanned!(@proc $v $span $($tt)*);
     };
     (@proc $v:ident $span:ident [ $($inner:tt)* ] $($tt:tt)*) => {
-        let mut tokens = ::std::vec::Vec::new();
+        #[allow(unused_mut)]
+        let mut tokens = ::std::vec::Vec::<::proc_macro::TokenTree>::new();
         quote_spanned!(@proc tokens $span $($inner)*);
         $v.push(::proc_macro::TokenTree::Group(::proc_macro::Group::new(
             ::proc_macro::Delimiter::Bracket,
@@ -90,7 +100,8 @@ macro_rules! quote_spanned {
         quote_spanned!(@proc $v $span $($tt)*);
     };
     (@proc $v:ident $span:ident { $($inner:tt)* } $($tt:tt)*) => {
-        let mut tokens = ::std::vec::Vec::new();
+        #[allow(unused_mut)]
+        let mut tokens = ::std::vec::Vec::<::proc_macro::TokenTree>::new();
         quote_spanned!(@proc tokens $span $($inner)*);
         $v.push(::proc_macro::TokenTree::Group(::proc_macro::Group::new(
             ::proc_macro::Delimiter::Brace,
@@ -178,3 +189,14 @@ macro_rules! quote {
         quote_spanned!(::proc_macro::Span::mixed_site() => $($tt)*)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn literal_hash() {
+        // Creating tokens needs the context of a procedural macro, which tests do not have, hence
+        // the expansion is only compiled. `name` is not in scope, so this also checks that `##name`
+        // is not taken as an interpolation.
+        let _ = || quote!(##name);
+    }
+}
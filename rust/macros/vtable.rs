@@ -1,10 +1,216 @@
 // SPDX-License-Identifier: GPL-2.0
 
+use crate::helpers::split_commas;
 use proc_macro::{Delimiter, Group, TokenStream, TokenTree};
 use std::collections::HashSet;
 use std::fmt::Write;
 
-pub(crate) fn vtable(_attr: TokenStream, ts: TokenStream) -> TokenStream {
+/// A field of a C vtable, as given to `#[vtable(fields(...))]`.
+struct VtableField {
+    /// The name of the method, which is also the name of the field.
+    name: String,
+    /// The function pointer type of the field, without the `Option`.
+    ty: TokenStream,
+    /// The function stored in the field if the method is implemented.
+    value: TokenStream,
+}
+
+const FIELDS_USAGE: &str =
+    "Expected a list of fields, e.g. `fields(open: OpenFn = open_callback::<Self>)`";
+
+/// Parses the `name: Type = value` entries of the `fields(...)` argument.
+fn parse_fields(input: TokenStream) -> Vec<VtableField> {
+    let mut fields = Vec::new();
+    for item in split_commas(input) {
+        let [TokenTree::Ident(name), TokenTree::Punct(colon), rest @ ..] = &item[..] else {
+            panic!("{}", FIELDS_USAGE);
+        };
+        if colon.as_char() != ':' {
+            panic!("{}", FIELDS_USAGE);
+        }
+        // The type ends at the first `=` that is not nested in `<>`.
+        let mut nesting = 0;
+        let mut after_minus = false;
+        let eq = rest.iter().position(|tt| {
+            let found = match tt {
+                TokenTree::Punct(p) if p.as_char() == '<' => {
+                    nesting += 1;
+                    false
+                }
+                TokenTree::Punct(p) if p.as_char() == '>' && !after_minus => {
+                    nesting -= 1;
+                    false
+                }
+                TokenTree::Punct(p) => p.as_char() == '=' && nesting == 0,
+                _ => false,
+            };
+            after_minus = matches!(tt, TokenTree::Punct(p) if p.as_char() == '-');
+            found
+        });
+        let Some(eq) = eq.filter(|&eq| eq > 0 && eq + 1 < rest.len()) else {
+            panic!("{}", FIELDS_USAGE);
+        };
+        let name = name.to_string();
+        if fields.iter().any(|f: &VtableField| f.name == name) {
+            panic!("Duplicated field `{}`", name);
+        }
+        fields.push(VtableField {
+            name,
+            ty: rest[..eq].iter().cloned().collect(),
+            value: rest[eq + 1..].iter().cloned().collect(),
+        });
+    }
+    fields
+}
+
+/// The arguments of the `#[vtable]` attribute.
+#[derive(Default)]
+struct VtableArgs {
+    /// The methods that are optional, if only some of them are.
+    optional: Option<Vec<String>>,
+    /// Whether to generate `method_names()`.
+    reflect: bool,
+    /// Whether default implementations must call `build_error!(VTABLE_DEFAULT_ERROR)`.
+    enforce_default: bool,
+    /// Whether to generate `VTABLE_REQUIRED_METHODS`.
+    scaffold: bool,
+    /// The fields of the C vtable to generate initializers for.
+    fields: Option<Vec<VtableField>>,
+    /// The lints allowed on the generated items.
+    allow: Vec<String>,
+    /// Whether to generate an implementation for references that forwards to this one.
+    forward_ref: bool,
+    /// The name of the C struct of the vtable.
+    c_type: Option<String>,
+}
+
+impl VtableArgs {
+    fn parse(attr: TokenStream) -> Self {
+        const EXPECTED_KEYS: &[&str] = &[
+            "optional",
+            "reflect",
+            "enforce_default",
+            "scaffold",
+            "fields",
+            "allow",
+            "forward_ref",
+            "c_type",
+        ];
+
+        let mut args = VtableArgs::default();
+        let mut it = attr.into_iter();
+        while let Some(token) = it.next() {
+            let key = match token {
+                TokenTree::Ident(ident) => ident.to_string(),
+                _ => panic!("Expected one of {:?}", EXPECTED_KEYS),
+            };
+            match key.as_str() {
+                "optional" => {
+                    let group = match it.next() {
+                        Some(TokenTree::Group(group))
+                            if group.delimiter() == Delimiter::Parenthesis =>
+                        {
+                            group
+                        }
+                        _ => panic!("Expected a list of methods, e.g. `optional(foo, bar)`"),
+                    };
+                    let mut methods = Vec::new();
+                    for token in group.stream() {
+                        match token {
+                            TokenTree::Ident(ident) => methods.push(ident.to_string()),
+                            TokenTree::Punct(punct) if punct.as_char() == ',' => (),
+                            _ => panic!("Expected a list of methods, e.g. `optional(foo, bar)`"),
+                        }
+                    }
+                    args.optional = Some(methods);
+                }
+                "reflect" => args.reflect = true,
+                "enforce_default" => args.enforce_default = true,
+                "scaffold" => args.scaffold = true,
+                "forward_ref" => args.forward_ref = true,
+                "c_type" => {
+                    const C_TYPE_USAGE: &str =
+                        "Expected the name of a C struct, e.g. `c_type = \"file_operations\"`";
+                    if !matches!(it.next(), Some(TokenTree::Punct(p)) if p.as_char() == '=') {
+                        panic!("{}", C_TYPE_USAGE);
+                    }
+                    let c_type = match it.next() {
+                        Some(TokenTree::Literal(lit)) => lit.to_string(),
+                        _ => panic!("{}", C_TYPE_USAGE),
+                    };
+                    let c_type = c_type
+                        .strip_prefix('"')
+                        .and_then(|c_type| c_type.strip_suffix('"'))
+                        .filter(|c_type| {
+                            c_type.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                                && c_type
+                                    .bytes()
+                                    .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+                        })
+                        .unwrap_or_else(|| panic!("{}", C_TYPE_USAGE));
+                    args.c_type = Some(c_type.to_owned());
+                }
+                "fields" => match it.next() {
+                    Some(TokenTree::Group(group))
+                        if group.delimiter() == Delimiter::Parenthesis =>
+                    {
+                        args.fields = Some(parse_fields(group.stream()));
+                    }
+                    _ => panic!("{}", FIELDS_USAGE),
+                },
+                "allow" => match it.next() {
+                    Some(TokenTree::Group(group))
+                        if group.delimiter() == Delimiter::Parenthesis =>
+                    {
+                        args.allow = split_commas(group.stream())
+                            .into_iter()
+                            .map(|lint| lint.into_iter().collect::<TokenStream>().to_string())
+                            .collect();
+                        if args.allow.is_empty() {
+                            panic!("Expected a list of lints, e.g. `allow(dead_code)`");
+                        }
+                    }
+                    _ => panic!("Expected a list of lints, e.g. `allow(dead_code)`"),
+                },
+                _ => panic!(
+                    "Unknown argument \"{}\". Valid arguments are: {:?}.",
+                    key, EXPECTED_KEYS
+                ),
+            }
+            match it.next() {
+                Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => (),
+                None => break,
+                _ => panic!("Expected ',' or end of arguments"),
+            }
+        }
+        args
+    }
+}
+
+/// Returns whether `body` contains a `build_error!(VTABLE_DEFAULT_ERROR)` call.
+///
+/// This is a purely syntactic check, the macro cannot know whether the call is reachable or
+/// whether `build_error` and `VTABLE_DEFAULT_ERROR` refer to the kernel items.
+fn calls_default_error(body: TokenStream) -> bool {
+    let tokens: Vec<_> = body.into_iter().collect();
+    tokens.iter().enumerate().any(|(i, token)| match token {
+        TokenTree::Ident(ident) if ident.to_string() == "build_error" => {
+            matches!(
+                (tokens.get(i + 1), tokens.get(i + 2)),
+                (Some(TokenTree::Punct(punct)), Some(TokenTree::Group(args)))
+                    if punct.as_char() == '!' && args.stream().into_iter().any(|arg| {
+                        matches!(arg, TokenTree::Ident(ident)
+                            if ident.to_string() == "VTABLE_DEFAULT_ERROR")
+                    })
+            )
+        }
+        TokenTree::Group(group) => calls_default_error(group.stream()),
+        _ => false,
+    })
+}
+
+pub(crate) fn vtable(attr: TokenStream, ts: TokenStream) -> TokenStream {
+    let args = VtableArgs::parse(attr);
     let mut tokens: Vec<_> = ts.into_iter().collect();
 
     // Scan for the `trait` or `impl` keyword.
@@ -29,15 +235,36 @@ pub(crate) fn vtable(_attr: TokenStream, ts: TokenStream) -> TokenStream {
     let mut body_it = body.stream().into_iter();
     let mut functions = Vec::new();
     let mut consts = HashSet::new();
+    // Whether the previous token is `unsafe`, to include it in the signature of a method.
+    let mut after_unsafe = false;
     while let Some(token) = body_it.next() {
+        let is_unsafe = after_unsafe;
+        after_unsafe = matches!(&token, TokenTree::Ident(ident) if ident.to_string() == "unsafe");
         match token {
             TokenTree::Ident(ident) if ident.to_string() == "fn" => {
-                let fn_name = match body_it.next() {
-                    Some(TokenTree::Ident(ident)) => ident.to_string(),
+                let name_token = match body_it.next() {
+                    Some(TokenTree::Ident(ident)) => ident,
                     // Possibly we've encountered a fn pointer type instead.
                     _ => continue,
                 };
-                functions.push(fn_name);
+                let fn_name = name_token.to_string();
+                let mut signature: Vec<TokenTree> = vec![ident.into(), name_token.into()];
+                // The signature ends with either the body or a `;`.
+                let fn_body = body_it.by_ref().find_map(|token| match token {
+                    TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                        Some(Some(group))
+                    }
+                    TokenTree::Punct(punct) if punct.as_char() == ';' => Some(None),
+                    token => {
+                        signature.push(token);
+                        None
+                    }
+                });
+                let mut signature = signature.into_iter().collect::<TokenStream>().to_string();
+                if is_unsafe {
+                    signature.insert_str(0, "unsafe ");
+                }
+                functions.push((fn_name, fn_body.flatten(), signature));
             }
             TokenTree::Ident(ident) if ident.to_string() == "const" => {
                 let const_name = match body_it.next() {
@@ -51,46 +278,566 @@ pub(crate) fn vtable(_attr: TokenStream, ts: TokenStream) -> TokenStream {
         }
     }
 
+    // The generated items get the `#[allow(...)]` of the user, if any.
+    let allow = if args.allow.is_empty() {
+        String::new()
+    } else {
+        format!("#[allow({})]", args.allow.join(", "))
+    };
     let mut const_items;
+    let mut fields_impl = TokenStream::new();
+    let mut forward_impl = TokenStream::new();
     if is_trait {
-        const_items = "
+        if args.fields.is_some() || args.forward_ref {
+            panic!("`fields` and `forward_ref` can only be used on an impl block");
+        }
+        const_items = format!(
+            "
                 /// A marker to prevent implementors from forgetting to use [`#[vtable]`](vtable)
                 /// attribute when implementing this trait.
+                {allow}
                 const USE_VTABLE_ATTR: ();
-        "
-        .to_owned();
+            "
+        );
+
+        if let Some(optional) = &args.optional {
+            for f in optional {
+                if !functions.iter().any(|(name, _, _)| name == f) {
+                    panic!("Optional method `{}` is not a method of the trait", f);
+                }
+            }
+        }
+
+        if args.scaffold {
+            write!(const_items, "{allow} {}", scaffold(&tokens, &functions)).unwrap();
+        }
+
+        if let Some(c_type) = &args.c_type {
+            write!(
+                const_items,
+                "/// The name of the C struct of the vtable, i.e. `struct {c_type}`.
+                {allow}
+                const C_VTABLE_TYPE: &'static str = \"{c_type}\";",
+            )
+            .unwrap();
+        }
+
+        let methods: Vec<_> = functions
+            .iter()
+            .map(|(f, _, _)| (f.clone(), format!("HAS_{}", f.to_uppercase())))
+            .collect();
 
-        for f in functions {
+        for (f, fn_body, _) in functions {
             let gen_const_name = format!("HAS_{}", f.to_uppercase());
             // Skip if it's declared already -- this allows user override.
             if consts.contains(&gen_const_name) {
                 continue;
             }
+            let is_optional = match &args.optional {
+                Some(optional) => optional.contains(&f),
+                None => true,
+            };
+            if !is_optional && fn_body.is_some() {
+                panic!(
+                    "Method `{}` has a default implementation, but is not listed as optional",
+                    f
+                );
+            }
+            if args.enforce_default {
+                if let Some(fn_body) = &fn_body {
+                    if !calls_default_error(fn_body.stream()) {
+                        panic!(
+                            "The default implementation of method `{}` must call \
+                            `build_error!(VTABLE_DEFAULT_ERROR)`",
+                            f
+                        );
+                    }
+                }
+            }
             // We don't know on the implementation-site whether a method is required or provided
-            // so we have to generate a const for all methods.
+            // so we have to generate a const for all methods. Required methods are always
+            // implemented, so their const is hidden.
+            if is_optional {
+                write!(
+                    const_items,
+                    "/// Indicates if the [`{f}`](Self::{f}) method is overridden by the implementor.
+                    {allow}
+                    const {gen_const_name}: bool = false;",
+                )
+                .unwrap();
+            } else {
+                write!(
+                    const_items,
+                    "#[doc(hidden)]
+                    {allow}
+                    const {gen_const_name}: bool = true;",
+                )
+                .unwrap();
+            }
+            consts.insert(gen_const_name);
+        }
+
+        if !consts.contains("VTABLE_IS_COMPLETE") {
+            let all: Vec<_> = methods
+                .iter()
+                .map(|(_, gen_const_name)| format!("Self::{gen_const_name}"))
+                .collect();
+            let all = if all.is_empty() {
+                "true".to_owned()
+            } else {
+                all.join(" && ")
+            };
             write!(
                 const_items,
-                "/// Indicates if the `{f}` method is overridden by the implementor.
-                const {gen_const_name}: bool = false;",
+                "/// Indicates if every method is implemented, i.e. no entry of the vtable is NULL.
+                {allow}
+                const VTABLE_IS_COMPLETE: bool = {all};",
+            )
+            .unwrap();
+        }
+
+        if !consts.contains("VTABLE_ASSERT_COMPLETE") {
+            write!(
+                const_items,
+                "/// Fails to compile when evaluated if not every method is implemented, see
+                /// [`VTABLE_IS_COMPLETE`](Self::VTABLE_IS_COMPLETE).
+                {allow}
+                const VTABLE_ASSERT_COMPLETE: () = ::core::assert!(
+                    Self::VTABLE_IS_COMPLETE,
+                    \"not every method of the vtable is implemented\"
+                );",
+            )
+            .unwrap();
+        }
+
+        if args.reflect {
+            // The names are collected in a const, so that they reflect the `HAS_*` constants of
+            // the implementation.
+            let entries: String = methods
+                .iter()
+                .map(|(f, gen_const_name)| format!("(\"{f}\", Self::{gen_const_name}),"))
+                .collect();
+            write!(
+                const_items,
+                "#[doc(hidden)]
+                {allow}
+                const VTABLE_METHOD_NAMES: ([&'static str; {len}], usize) = {{
+                    let methods: [(&str, bool); {len}] = [{entries}];
+                    let mut names = [\"\"; {len}];
+                    let mut len = 0;
+                    let mut i = 0;
+                    while i < methods.len() {{
+                        if methods[i].1 {{
+                            names[len] = methods[i].0;
+                            len += 1;
+                        }}
+                        i += 1;
+                    }}
+                    (names, len)
+                }};
+
+                /// The number of entries of the vtable that are not NULL, i.e. of the methods
+                /// implemented by the implementor, including the required ones.
+                {allow}
+                const LIVE_METHOD_COUNT: usize = Self::VTABLE_METHOD_NAMES.1;
+
+                /// Returns the names of the methods overridden by the implementor.
+                {allow}
+                fn method_names() -> &'static [&'static str]
+                where
+                    Self: Sized,
+                {{
+                    let (names, len) = &Self::VTABLE_METHOD_NAMES;
+                    names.split_at(*len).0
+                }}",
+                len = methods.len(),
             )
             .unwrap();
-            consts.insert(gen_const_name);
         }
     } else {
-        const_items = "const USE_VTABLE_ATTR: () = ();".to_owned();
+        if args.optional.is_some()
+            || args.reflect
+            || args.enforce_default
+            || args.scaffold
+            || args.c_type.is_some()
+        {
+            panic!(
+                "`optional`, `reflect`, `enforce_default`, `scaffold` and `c_type` can only be used \
+                on a trait"
+            );
+        }
+        const_items = format!("{allow} const USE_VTABLE_ATTR: () = ();");
 
-        for f in functions {
+        for (f, _, _) in functions {
             let gen_const_name = format!("HAS_{}", f.to_uppercase());
             if consts.contains(&gen_const_name) {
                 continue;
             }
-            write!(const_items, "const {gen_const_name}: bool = true;").unwrap();
+            write!(const_items, "{allow} const {gen_const_name}: bool = true;").unwrap();
+        }
+
+        if let Some(fields) = &args.fields {
+            fields_impl = vtable_fields(&tokens, fields, &allow);
+        }
+
+        if args.forward_ref {
+            forward_impl = forward_ref(&tokens, &body, &allow);
         }
     }
 
+    // The generated items are placed in the body of the trait or impl block, so they are always
+    // associated items and cannot conflict between implementations for different types.
     let new_body = vec![const_items.parse().unwrap(), body.stream()]
         .into_iter()
         .collect();
     tokens.push(TokenTree::Group(Group::new(Delimiter::Brace, new_body)));
-    tokens.into_iter().collect()
+    tokens
+        .into_iter()
+        .chain(fields_impl)
+        .chain(forward_impl)
+        .collect()
+}
+
+/// Generates the `VTABLE_REQUIRED_METHODS` constant of `#[vtable(scaffold)]`.
+///
+/// `header` is the trait without its body. The documentation of the constant contains a skeleton
+/// implementation of the required methods, i.e. those without a default implementation.
+fn scaffold(header: &[TokenTree], functions: &[(String, Option<Group>, String)]) -> String {
+    let trait_name = header
+        .iter()
+        .skip_while(|tt| !matches!(tt, TokenTree::Ident(i) if i.to_string() == "trait"))
+        .nth(1)
+        .map(|tt| tt.to_string())
+        .expect("cannot locate the name of the trait");
+    let required: Vec<_> = functions
+        .iter()
+        .filter(|(_, fn_body, _)| fn_body.is_none())
+        .map(|(_, _, signature)| signature)
+        .collect();
+    let mut docs = vec![
+        "The signatures of the methods without a default implementation.".to_owned(),
+        String::new(),
+        "An implementation can start from this skeleton:".to_owned(),
+        String::new(),
+        "```ignore".to_owned(),
+        "#[vtable]".to_owned(),
+        format!("impl {trait_name} for MyType {{"),
+    ];
+    for signature in &required {
+        docs.push(format!("    {signature} {{"));
+        docs.push("        todo!()".to_owned());
+        docs.push("    }".to_owned());
+    }
+    docs.push("}".to_owned());
+    docs.push("```".to_owned());
+    let docs: String = docs
+        .iter()
+        .map(|line| format!("#[doc = {line:?}]"))
+        .collect();
+    let signatures: String = required.iter().map(|s| format!("{s:?},")).collect();
+    format!("{docs} const VTABLE_REQUIRED_METHODS: &'static [&'static str] = &[{signatures}];")
+}
+
+/// The parts of the header of an impl block of a trait.
+struct ImplHeader {
+    /// Whether the impl block is `unsafe`.
+    is_unsafe: bool,
+    /// The generics of the impl block, including the `<>`.
+    generics: String,
+    /// The path of the implemented trait.
+    trait_path: String,
+    /// The implementing type.
+    self_ty: String,
+    /// The where clause, if any.
+    where_clause: String,
+}
+
+/// Splits `header`, the impl block without its body, into its parts.
+///
+/// `arg` is the argument of `#[vtable]` that needs them, for the error message.
+fn impl_header(header: &[TokenTree], arg: &str) -> ImplHeader {
+    let is_ident =
+        |tt: &TokenTree, name: &str| matches!(tt, TokenTree::Ident(i) if i.to_string() == name);
+    let impl_pos = header
+        .iter()
+        .position(|tt| is_ident(tt, "impl"))
+        .expect("cannot locate `impl` keyword");
+    // The generics of the impl block, including the `<>`.
+    let mut generics_end = impl_pos + 1;
+    if matches!(header.get(generics_end), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+        let mut nesting = 0;
+        for tt in &header[impl_pos + 1..] {
+            generics_end += 1;
+            match tt {
+                TokenTree::Punct(p) if p.as_char() == '<' => nesting += 1,
+                TokenTree::Punct(p) if p.as_char() == '>' => {
+                    nesting -= 1;
+                    if nesting == 0 {
+                        break;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    let for_pos = header[generics_end..]
+        .iter()
+        .position(|tt| is_ident(tt, "for"))
+        .map(|pos| generics_end + pos)
+        .unwrap_or_else(|| panic!("`{arg}` can only be used on an impl block of a trait"));
+    // A `where` cannot appear in the implementing type, so the first one starts the where clause.
+    let where_pos = header[for_pos..]
+        .iter()
+        .position(|tt| is_ident(tt, "where"))
+        .map_or(header.len(), |pos| for_pos + pos);
+    let to_string =
+        |tokens: &[TokenTree]| tokens.iter().cloned().collect::<TokenStream>().to_string();
+    ImplHeader {
+        is_unsafe: header[..impl_pos].iter().any(|tt| is_ident(tt, "unsafe")),
+        generics: to_string(&header[impl_pos + 1..generics_end]),
+        trait_path: to_string(&header[generics_end..for_pos]),
+        self_ty: to_string(&header[for_pos + 1..where_pos]),
+        where_clause: to_string(&header[where_pos..]),
+    }
+}
+
+/// Generates the `VTABLE_FIELDS` constant of `#[vtable(fields(...))]`.
+///
+/// `header` is the impl block without its body. The constant is placed in an inherent impl block
+/// of the implementing type, since a trait impl cannot contain items that are not in the trait.
+fn vtable_fields(header: &[TokenTree], fields: &[VtableField], allow: &str) -> TokenStream {
+    let ImplHeader {
+        generics,
+        trait_path,
+        self_ty,
+        where_clause,
+        ..
+    } = impl_header(header, "fields");
+
+    let types: String = fields
+        .iter()
+        .map(|f| format!("Option<{}>,", f.ty))
+        .collect();
+    let values: String = fields
+        .iter()
+        .map(|f| {
+            format!(
+                "if <Self as {trait_path}>::HAS_{has} {{ Some({value}) }} else {{ None }},",
+                has = f.name.to_uppercase(),
+                value = f.value,
+            )
+        })
+        .collect();
+    format!(
+        "
+            impl{generics} {self_ty} {where_clause} {{
+                /// The fields of the C vtable, in the order given to `#[vtable(fields(...))]`.
+                ///
+                /// Fields of methods that are not implemented are `None`.
+                #[allow(dead_code)]
+                {allow}
+                pub(crate) const VTABLE_FIELDS: ({types}) = ({values});
+            }}
+        "
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Returns whether `tokens` contain the `Self` type.
+fn mentions_self(tokens: &[TokenTree]) -> bool {
+    tokens.iter().any(|tt| match tt {
+        TokenTree::Ident(i) => i.to_string() == "Self",
+        TokenTree::Group(g) => mentions_self(&g.stream().into_iter().collect::<Vec<_>>()),
+        _ => false,
+    })
+}
+
+/// Generates the implementation for `&T` of `#[vtable(forward_ref)]`.
+///
+/// `header` is the impl block without its body. Every item of `body` is forwarded to the
+/// implementation for `T`, including the `HAS_*` constants, so both have the same vtable.
+fn forward_ref(header: &[TokenTree], body: &Group, allow: &str) -> TokenStream {
+    let ImplHeader {
+        is_unsafe,
+        generics,
+        trait_path,
+        self_ty,
+        where_clause,
+    } = impl_header(header, "forward_ref");
+    let generics = generics.trim_start_matches('<').trim_end_matches('>');
+    let target = format!("<{self_ty} as {trait_path}>");
+    let to_string =
+        |tokens: &[TokenTree]| tokens.iter().cloned().collect::<TokenStream>().to_string();
+    let is_ident =
+        |tt: &TokenTree, name: &str| matches!(tt, TokenTree::Ident(i) if i.to_string() == name);
+    let is_punct = |tt: &TokenTree, c: char| matches!(tt, TokenTree::Punct(p) if p.as_char() == c);
+
+    // The items of the body end with a `;` or, for methods, with their body.
+    let mut items = vec![Vec::new()];
+    for tt in body.stream() {
+        let is_end = match &tt {
+            TokenTree::Punct(p) => p.as_char() == ';',
+            TokenTree::Group(g) => g.delimiter() == Delimiter::Brace,
+            _ => false,
+        };
+        items.last_mut().unwrap().push(tt);
+        if is_end {
+            items.push(Vec::new());
+        }
+    }
+
+    let mut consts = HashSet::new();
+    let mut methods = Vec::new();
+    let mut forwarded = String::new();
+    for item in items {
+        // Skip the attributes, e.g. documentation.
+        let mut item = &item[..];
+        while let [TokenTree::Punct(p), TokenTree::Group(_), rest @ ..] = item {
+            if p.as_char() != '#' {
+                break;
+            }
+            item = rest;
+        }
+        let Some(kind) = item.iter().position(|tt| {
+            ["fn", "type", "const"]
+                .iter()
+                .any(|kind| is_ident(tt, kind))
+        }) else {
+            continue;
+        };
+        let name = match item.get(kind + 1) {
+            Some(TokenTree::Ident(name)) => name.to_string(),
+            _ => continue,
+        };
+        match &*item[kind].to_string() {
+            "type" => {
+                if !item.get(kind + 2).is_some_and(|tt| is_punct(tt, '=')) {
+                    panic!("`forward_ref` cannot forward the generic associated type `{name}`");
+                }
+                write!(forwarded, "type {name} = {target}::{name};").unwrap();
+            }
+            "const" => {
+                // The type is between the `:` and the `=`.
+                let ty = item[kind + 3..]
+                    .iter()
+                    .take_while(|tt| !is_punct(tt, '='))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                write!(
+                    forwarded,
+                    "const {name}: {} = {target}::{name};",
+                    to_string(&ty)
+                )
+                .unwrap();
+                consts.insert(name);
+            }
+            _ => {
+                let qualifiers = to_string(&item[..kind]);
+                let Some((params_pos, params)) =
+                    item.iter().enumerate().find_map(|(pos, tt)| match tt {
+                        TokenTree::Group(g) if g.delimiter() == Delimiter::Parenthesis => {
+                            Some((pos, g))
+                        }
+                        _ => None,
+                    })
+                else {
+                    continue;
+                };
+                let method_generics = to_string(&item[kind + 2..params_pos]);
+                // The return type and the where clause, up to the body.
+                let rest = &item[params_pos + 1..item.len() - 1];
+                let ret_end = rest
+                    .iter()
+                    .position(|tt| is_ident(tt, "where"))
+                    .unwrap_or(rest.len());
+                if mentions_self(&rest[..ret_end]) {
+                    panic!(
+                        "`forward_ref` cannot forward method `{name}`, since its return type \
+                        uses `Self`"
+                    );
+                }
+                let mut new_params = Vec::new();
+                let mut args = Vec::new();
+                for (i, param) in split_commas(params.stream()).into_iter().enumerate() {
+                    if param.iter().any(|tt| is_ident(tt, "self")) {
+                        // Only `&self` and `&'a self` can be forwarded, since `&T` only gives
+                        // shared access to `T`.
+                        let is_ref = param.first().is_some_and(|tt| is_punct(tt, '&'))
+                            && !param.iter().any(|tt| is_ident(tt, "mut"))
+                            && param.last().is_some_and(|tt| is_ident(tt, "self"));
+                        if !is_ref {
+                            panic!(
+                                "`forward_ref` cannot forward method `{name}`, only `&self` \
+                                receivers can be forwarded"
+                            );
+                        }
+                        new_params.push(to_string(&param));
+                        args.push("*self".to_owned());
+                        continue;
+                    }
+                    // The type follows the first `:` that is not nested in a group.
+                    let colon = param
+                        .iter()
+                        .position(|tt| is_punct(tt, ':'))
+                        .expect("cannot locate the type of a parameter");
+                    let ty = &param[colon + 1..];
+                    if mentions_self(ty) {
+                        panic!(
+                            "`forward_ref` cannot forward method `{name}`, since its parameters \
+                            use `Self`"
+                        );
+                    }
+                    new_params.push(format!("__arg{i}: {}", to_string(ty)));
+                    args.push(format!("__arg{i}"));
+                }
+                let call = format!("{target}::{name}({})", args.join(", "));
+                let call = if qualifiers.split_whitespace().any(|q| q == "unsafe") {
+                    format!(
+                        "// SAFETY: The caller upholds the safety requirements of the method.
+                        unsafe {{ {call} }}"
+                    )
+                } else {
+                    call
+                };
+                write!(
+                    forwarded,
+                    "{qualifiers} fn {name}{method_generics}({params}) {rest} {{ {call} }}",
+                    params = new_params.join(", "),
+                    rest = to_string(rest),
+                )
+                .unwrap();
+                methods.push(name);
+            }
+        }
+    }
+    // Like for the implementation for `T`, the `HAS_*` constants that are not given are generated.
+    let has_consts: String = methods
+        .iter()
+        .map(|name| format!("HAS_{}", name.to_uppercase()))
+        .filter(|gen_const_name| !consts.contains(gen_const_name))
+        .map(|gen_const_name| {
+            format!("{allow} const {gen_const_name}: bool = {target}::{gen_const_name};")
+        })
+        .collect();
+    let unsafe_ = if is_unsafe {
+        "// SAFETY: The requirements of the trait are upheld by the implementation for `T`.
+        unsafe"
+    } else {
+        ""
+    };
+    format!(
+        "
+            {unsafe_} impl<'__vtable_ref, {generics}> {trait_path} for &'__vtable_ref {self_ty}
+            {where_clause}
+            {{
+                {allow}
+                const USE_VTABLE_ATTR: () = ();
+                {has_consts}
+                {forwarded}
+            }}
+        "
+    )
+    .parse()
+    .unwrap()
 }
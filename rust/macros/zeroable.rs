@@ -1,7 +1,321 @@
 // SPDX-License-Identifier: GPL-2.0
 
-use crate::helpers::{parse_generics, Generics};
-use proc_macro::{TokenStream, TokenTree};
+use crate::helpers::{compile_error, parse_generics, split_commas, Assertions, Generics};
+use proc_macro::{Delimiter, Span, TokenStream, TokenTree};
+
+/// The integer types that can be used in `#[repr(...)]` of an enum.
+const REPR_INTS: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+];
+
+/// Returns the integer type given in the `#[repr(...)]` attribute of `attrs`, if any.
+fn repr_int(attrs: &[TokenTree]) -> Option<String> {
+    attrs.iter().find_map(|tt| {
+        let TokenTree::Group(attr) = tt else {
+            return None;
+        };
+        let mut attr = attr.stream().into_iter();
+        match (attr.next(), attr.next()) {
+            (Some(TokenTree::Ident(i)), Some(TokenTree::Group(reprs)))
+                if i.to_string() == "repr" =>
+            {
+                reprs.stream().into_iter().find_map(|tt| match tt {
+                    TokenTree::Ident(i) if REPR_INTS.contains(&i.to_string().as_str()) => {
+                        Some(i.to_string())
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Returns the options given in the `#[zeroable(...)]` attributes of `attrs`.
+fn options(attrs: &[TokenTree]) -> Result<Vec<String>, TokenStream> {
+    let mut options = Vec::new();
+    for tt in attrs {
+        let TokenTree::Group(attr) = tt else {
+            continue;
+        };
+        let mut attr = attr.stream().into_iter();
+        match (attr.next(), attr.next()) {
+            (Some(TokenTree::Ident(i)), args) if i.to_string() == "zeroable" => {
+                let Some(TokenTree::Group(args)) = args else {
+                    return Err(compile_error("Expected `#[zeroable(...)]`."));
+                };
+                for option in split_commas(args.stream()) {
+                    match option.as_slice() {
+                        [TokenTree::Ident(i)]
+                            if matches!(i.to_string().as_str(), "deny_ptr" | "deny_drop") =>
+                        {
+                            options.push(i.to_string())
+                        }
+                        _ => {
+                            return Err(compile_error(&format!(
+                                "Unknown `zeroable` option `{}`, expected `deny_ptr` or \
+                                    `deny_drop`.",
+                                option.into_iter().collect::<TokenStream>()
+                            )))
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(options)
+}
+
+/// Returns the type of `field`, a field of a struct or a union with the delimiter `delimiter`.
+///
+/// Named fields have their type after the first `:` that is not nested in a group, e.g. in
+/// `pub(in a::b)`. The fields of a tuple struct have no name, their type follows the attributes and
+/// the visibility.
+fn field_type(field: &[TokenTree], delimiter: Delimiter) -> Option<&[TokenTree]> {
+    if delimiter == Delimiter::Brace {
+        let colon = field
+            .iter()
+            .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ':'))?;
+        return Some(&field[colon + 1..]);
+    }
+    let mut ty = field;
+    loop {
+        match ty {
+            [TokenTree::Punct(p), TokenTree::Group(_), rest @ ..] if p.as_char() == '#' => {
+                ty = rest
+            }
+            // `pub (u8, u16)` is a public tuple type, only a group that is a path is a visibility.
+            [TokenTree::Ident(i), TokenTree::Group(g), rest @ ..]
+                if i.to_string() == "pub"
+                    && g.delimiter() == Delimiter::Parenthesis
+                    && matches!(
+                        g.stream().into_iter().next(),
+                        Some(TokenTree::Ident(i))
+                            if matches!(i.to_string().as_str(), "crate" | "self" | "super" | "in")
+                    ) =>
+            {
+                ty = rest
+            }
+            [TokenTree::Ident(i), rest @ ..] if i.to_string() == "pub" => ty = rest,
+            [] => return None,
+            _ => return Some(ty),
+        }
+    }
+}
+
+/// Errors on the fields of `body` that are raw pointers or arrays of raw pointers.
+///
+/// Their all-zeroes bit pattern is the null pointer, which is valid, but may not be expected by
+/// the code using them.
+fn deny_ptr(body: &TokenTree) -> TokenStream {
+    let TokenTree::Group(body) = body else {
+        return TokenStream::new();
+    };
+    let mut errs = TokenStream::new();
+    for field in split_commas(body.stream()) {
+        let Some(ty) = field_type(&field, body.delimiter()) else {
+            continue;
+        };
+        if let Some(span) = raw_pointer(ty) {
+            errs.extend(
+                compile_error(
+                    "Raw pointer fields are not allowed with `#[zeroable(deny_ptr)]`, since \
+                        zeroing them makes them null. Use e.g. `Option<NonNull<T>>` instead.",
+                )
+                .into_iter()
+                .map(|mut tt| {
+                    tt.set_span(span);
+                    tt
+                }),
+            );
+        }
+    }
+    errs
+}
+
+/// Returns the span of the `*` of `ty` if it is a raw pointer type or an array of them.
+fn raw_pointer(ty: &[TokenTree]) -> Option<Span> {
+    match ty {
+        [TokenTree::Punct(p), ..] if p.as_char() == '*' => Some(p.span()),
+        // Types passed through `macro_rules!` fragments are wrapped in an invisible group.
+        [TokenTree::Group(g)] if matches!(g.delimiter(), Delimiter::Bracket | Delimiter::None) => {
+            raw_pointer(&g.stream().into_iter().collect::<Vec<_>>())
+        }
+        _ => None,
+    }
+}
+
+/// Errors if the type declared by `rest` implements `Drop`, used with `#[zeroable(deny_drop)]`.
+///
+/// Zeroing a value does not run its destructor, so a type with a `Drop` impl may not be meant to
+/// be zeroed. Whether a type implements `Drop` is found with an inherent associated constant,
+/// which takes precedence over the one of the blanket trait impl if the bound holds. Generic types
+/// are skipped without an error, since the bound can only be decided for concrete types.
+fn drop_check(rest: &[TokenTree], impl_generics: &[TokenTree]) -> TokenStream {
+    if !impl_generics.is_empty() {
+        return TokenStream::new();
+    }
+    let mut it = rest.iter();
+    it.by_ref().find(|tt| {
+        matches!(tt, TokenTree::Ident(i)
+            if matches!(i.to_string().as_str(), "struct" | "union" | "enum"))
+    });
+    let Some(TokenTree::Ident(name)) = it.next() else {
+        return TokenStream::new();
+    };
+    format!(
+        "
+            const _: () = {{
+                trait __NoDrop {{
+                    const HAS_DROP: bool = false;
+                }}
+                impl<T: ?::core::marker::Sized> __NoDrop for T {{}}
+                struct __DropCheck<T: ?::core::marker::Sized>(::core::marker::PhantomData<T>);
+                #[allow(drop_bounds)]
+                impl<T: ?::core::marker::Sized + ::core::ops::Drop> __DropCheck<T> {{
+                    const HAS_DROP: bool = true;
+                }}
+                ::core::assert!(
+                    !__DropCheck::<{name}>::HAS_DROP,
+                    \"`{name}` implements `Drop`, which is not allowed with \\
+                        `#[zeroable(deny_drop)]`.\"
+                );
+            }};
+        "
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Derives `Zeroable` for a fieldless enum.
+///
+/// The all-zeroes bit pattern is only valid if one of the variants has the discriminant zero and
+/// the discriminant type is fixed with `#[repr(...)]`, which is checked here.
+fn derive_enum(
+    rest: &[TokenTree],
+    impl_generics: &[TokenTree],
+    body: Option<TokenTree>,
+) -> TokenStream {
+    let mut it = rest.iter();
+    it.by_ref()
+        .find(|tt| matches!(tt, TokenTree::Ident(i) if i.to_string() == "enum"));
+    let Some(TokenTree::Ident(name)) = it.next() else {
+        return compile_error("Could not locate type name.");
+    };
+    if !impl_generics.is_empty() {
+        return compile_error("`Zeroable` cannot be derived for generic enums.");
+    }
+    let Some(repr) = repr_int(rest) else {
+        return compile_error(&format!(
+            "`Zeroable` can only be derived for enums with an integer `#[repr(...)]`, e.g. \
+                `#[repr(u8)]`, since the type of the discriminant of `{name}` is not fixed \
+                otherwise."
+        ));
+    };
+    let Some(TokenTree::Group(body)) = body else {
+        return compile_error("Could not locate the variants of the enum.");
+    };
+    let mut variants = Vec::new();
+    let mut body = body.stream().into_iter();
+    while let Some(tt) = body.next() {
+        match tt {
+            // Skip the attributes of the variant.
+            TokenTree::Punct(p) if p.as_char() == '#' => {
+                body.next();
+            }
+            TokenTree::Ident(variant) => {
+                // The discriminant, if any, is skipped up to the next variant.
+                let mut in_discriminant = false;
+                for tt in body.by_ref() {
+                    match tt {
+                        TokenTree::Punct(p) if p.as_char() == ',' => break,
+                        TokenTree::Punct(p) if p.as_char() == '=' => in_discriminant = true,
+                        TokenTree::Group(_) if !in_discriminant => {
+                            return compile_error(&format!(
+                                "`Zeroable` can only be derived for fieldless enums, but \
+                                    `{name}::{variant}` has fields."
+                            ));
+                        }
+                        _ => (),
+                    }
+                }
+                variants.push(format!("{name}::{variant} as {repr} == 0"));
+            }
+            _ => (),
+        }
+    }
+    let any_zero = if variants.is_empty() {
+        "false".to_string()
+    } else {
+        variants.join(" || ")
+    };
+    format!(
+        "
+            const _: () = ::core::assert!(
+                {any_zero},
+                \"`{name}` needs a variant with the discriminant zero to derive `Zeroable`.\"
+            );
+            // SAFETY: The enum has a fixed discriminant type and a variant with the discriminant
+            // zero, so the all-zeroes bit pattern is that variant.
+            unsafe impl ::kernel::init::Zeroable for {name} {{}}
+        "
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Derives `Zeroable` for a struct or a union.
+///
+/// The all-zeroes bit pattern of a struct or a union is only valid if it is valid for the type of
+/// every field, so all of them are required to implement `Zeroable`. For a union this is needed
+/// since any field can be read after the union has been zeroed.
+fn derive_fields(
+    rest: Vec<TokenTree>,
+    impl_generics: Vec<TokenTree>,
+    ty_generics: Vec<TokenTree>,
+    body: Option<TokenTree>,
+) -> TokenStream {
+    let mut it = rest.into_iter();
+    let Some(TokenTree::Ident(kind)) = it.by_ref().find(|tt| {
+        matches!(tt, TokenTree::Ident(i) if matches!(i.to_string().as_str(), "struct" | "union"))
+    }) else {
+        return compile_error("Could not locate type name.");
+    };
+    let Some(TokenTree::Ident(name)) = it.next() else {
+        return compile_error("Could not locate type name.");
+    };
+    // Only a `where` clause can follow the name, since the generics have been removed.
+    let where_clause: Vec<TokenTree> = it.collect();
+    let Some(TokenTree::Group(body)) = body else {
+        return compile_error(&format!("Could not locate the fields of the {kind}."));
+    };
+    // `quote!` does not support `<` and `>`, so the output is assembled from parsed strings and
+    // the tokens of the input.
+    let parse = |s: &str| s.parse::<TokenStream>().unwrap();
+    let mut assertions = Assertions::new(impl_generics.iter().cloned(), where_clause.clone());
+    for field in split_commas(body.stream()) {
+        let Some(ty) = field_type(&field, body.delimiter()) else {
+            return compile_error(&format!(
+                "Could not locate the type of a field of the {kind}."
+            ));
+        };
+        // The field type is passed on as-is, so errors about it not implementing `Zeroable` point
+        // at the field.
+        assertions.implements(ty.iter().cloned().collect(), "::kernel::init::Zeroable");
+    }
+    let mut res = parse("#[automatically_derived] unsafe impl<");
+    res.extend(impl_generics);
+    res.extend(parse("> ::kernel::init::Zeroable for"));
+    res.extend([TokenTree::Ident(name)]);
+    res.extend(parse("<"));
+    res.extend(ty_generics);
+    res.extend(parse(">"));
+    res.extend(where_clause);
+    res.extend(parse("{}"));
+    res.extend(assertions.build());
+    res
+}
 
 pub(crate) fn derive(input: TokenStream) -> TokenStream {
     let (
@@ -12,8 +326,44 @@ pub(crate) fn derive(input: TokenStream) -> TokenStream {
         },
         mut rest,
     ) = parse_generics(input);
-    // This should be the body of the struct `{...}`.
-    let last = rest.pop();
+    // This should be the body of the struct `{...}`. It is passed on as-is, so the field types
+    // keep their spans and errors about fields not implementing `Zeroable` point at the field.
+    let mut last = rest.pop();
+    // A tuple struct ends with `;`, its fields `(...)` follow the name, before the `where` clause.
+    if matches!(&last, Some(TokenTree::Punct(p)) if p.as_char() == ';') {
+        let fields = rest
+            .iter()
+            .position(|tt| matches!(tt, TokenTree::Ident(i) if i.to_string() == "struct"))
+            .map(|i| i + 2)
+            .filter(|&i| {
+                matches!(rest.get(i), Some(TokenTree::Group(g))
+                    if g.delimiter() == Delimiter::Parenthesis)
+            });
+        if let Some(fields) = fields {
+            last = Some(rest.remove(fields));
+        }
+    }
+    let options = match options(&rest) {
+        Ok(options) => options,
+        Err(err) => return err,
+    };
+    let mut errs = TokenStream::new();
+    if options.iter().any(|o| o == "deny_drop") {
+        errs.extend(drop_check(&rest, &impl_generics));
+    }
+    if rest
+        .iter()
+        .any(|tt| matches!(tt, TokenTree::Ident(i) if i.to_string() == "enum"))
+    {
+        let mut res = derive_enum(&rest, &impl_generics, last);
+        res.extend(errs);
+        return res;
+    }
+    if options.iter().any(|o| o == "deny_ptr") {
+        errs.extend(last.as_ref().map(deny_ptr).unwrap_or_default());
+    }
+    // A `where` clause stays in `rest` and is used as-is, the `Zeroable` bounds are only added to
+    // the generics below, so both are combined on the generated impl.
     // Now we insert `Zeroable` as a bound for every generic parameter in `impl_generics`.
     let mut new_impl_generics = Vec::with_capacity(impl_generics.len());
     // Are we inside of a generic where we want to add `Zeroable`?
@@ -38,6 +388,12 @@ pub(crate) fn derive(input: TokenStream) -> TokenStream {
                 in_generic = false;
                 new_impl_generics.push(tt);
             }
+            // If we find `const`, then we are entering a const generic parameter, e.g. the length
+            // of an array field. Its type is not a type parameter, so it must not be bounded.
+            TokenTree::Ident(i) if nested == 0 && i.to_string() == "const" => {
+                in_generic = false;
+                new_impl_generics.push(tt);
+            }
             TokenTree::Punct(p) if nested == 0 && p.as_char() == ':' => {
                 new_impl_generics.push(tt);
                 if in_generic {
@@ -61,13 +417,7 @@ pub(crate) fn derive(input: TokenStream) -> TokenStream {
     if in_generic && !inserted {
         new_impl_generics.extend(quote! { : ::kernel::init::Zeroable });
     }
-    quote! {
-        ::kernel::__derive_zeroable!(
-            parse_input:
-                @sig(#(#rest)*),
-                @impl_generics(#(#new_impl_generics)*),
-                @ty_generics(#(#ty_generics)*),
-                @body(#last),
-        );
-    }
+    let mut res = derive_fields(rest, new_impl_generics, ty_generics, last);
+    res.extend(errs);
+    res
 }